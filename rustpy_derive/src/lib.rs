@@ -0,0 +1,371 @@
+//! `#[derive(ToPyType, FromPyType)]` for [rustpy](../rustpy/index.html).
+//!
+//! Structs with named fields convert to a python `dict` keyed by field name.
+//! Extraction reads the keys of a `dict`, or the attributes of any other
+//! object such as a `namedtuple`, or the items of a plain tuple by the position
+//! of the field, which must then have one item per field. Tuple structs convert
+//! to a `tuple`, except for newtypes with a single field which convert like the
+//! wrapped value. Unit structs convert to `None`.
+//!
+//! Enums are externally tagged: a unit variant is the string of its name, any
+//! other variant a `dict` with the variant name as its only key and the
+//! fields, converted like a struct, as its value.
+//!
+//! Fields and variants accept `#[rustpy(...)]` attributes:
+//!
+//! * `rename = "name"` uses another key, attribute or variant name in python.
+//! * `default` fills a missing named field with `Default::default()` on
+//!   extraction.
+//! * `default = "path"` fills a missing named field by calling the function
+//!   `path`.
+//! * `skip` leaves the field out of python entirely and fills it with
+//!   `Default::default()` on extraction.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Generics,
+          Ident, LitStr, Path, Result};
+
+#[proc_macro_derive(ToPyType, attributes(rustpy))]
+pub fn derive_to_py_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    to_py_type(&input).unwrap_or_else(compile_error).into()
+}
+
+#[proc_macro_derive(FromPyType, attributes(rustpy))]
+pub fn derive_from_py_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_py_type(&input).unwrap_or_else(compile_error).into()
+}
+
+/// `compile_error!` invocations reporting `error`, unqualified unlike
+/// `Error::into_compile_error` so that they also resolve in 2015 edition
+/// crates, which have no `::core` path.
+fn compile_error(error: Error) -> TokenStream2 {
+    error.into_iter()
+         .map(|error| {
+             let message = error.to_string();
+             quote_spanned!(error.span()=> compile_error!(#message);)
+         })
+         .collect()
+}
+
+enum FieldDefault {
+    None,
+    Trait,
+    Function(Path),
+}
+
+struct FieldAttrs {
+    rename: Option<String>,
+    default: FieldDefault,
+    skip: bool,
+}
+
+/// A field or variant as seen from python.
+struct Member {
+    /// Field name or index on the rust side.
+    member: syn::Member,
+    /// Binding used when destructuring an enum variant.
+    binding: Ident,
+    /// Key, attribute or variant name on the python side.
+    name: String,
+    attrs: FieldAttrs,
+}
+
+fn parse_attrs(attrs: &[Attribute], allow_field_attrs: bool) -> Result<FieldAttrs> {
+    let mut result = FieldAttrs {
+        rename: None,
+        default: FieldDefault::None,
+        skip: false,
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("rustpy")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                result.rename = Some(name.value());
+            } else if allow_field_attrs && meta.path.is_ident("default") {
+                result.default = if meta.input.peek(syn::Token![=]) {
+                    let path: LitStr = meta.value()?.parse()?;
+                    FieldDefault::Function(path.parse()?)
+                } else {
+                    FieldDefault::Trait
+                };
+            } else if allow_field_attrs && meta.path.is_ident("skip") {
+                result.skip = true;
+            } else {
+                return Err(meta.error("unsupported rustpy attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+fn members(fields: &Fields) -> Result<Vec<Member>> {
+    fields.iter()
+          .enumerate()
+          .map(|(i, field)| {
+              let attrs = parse_attrs(&field.attrs, true)?;
+              if field.ident.is_none() && !matches!(attrs.default, FieldDefault::None) {
+                  return Err(Error::new_spanned(field,
+                                                "rustpy default is only supported on named \
+                                                 fields"));
+              }
+              let (member, name) = match field.ident {
+                  Some(ref ident) => (syn::Member::Named(ident.clone()), ident.to_string()),
+                  None => (syn::Member::Unnamed(i.into()), i.to_string()),
+              };
+              Ok(Member {
+                  member,
+                  binding: format_ident!("__field{}", i),
+                  name: attrs.rename.clone().unwrap_or(name),
+                  attrs,
+              })
+          })
+          .collect()
+}
+
+fn add_bounds(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    generics
+}
+
+/// Expression converting the given fields, already bound to references, to a
+/// python object.
+fn fields_to_py_object(fields: &Fields, members: &[Member]) -> TokenStream2 {
+    let included: Vec<&Member> = members.iter().filter(|m| !m.attrs.skip).collect();
+    match *fields {
+        Fields::Named(_) => {
+            let names = included.iter().map(|m| &m.name);
+            let bindings = included.iter().map(|m| &m.binding);
+            quote! {{
+                let dict = ::rustpy::derive::new_dict(state)?;
+                #(::rustpy::derive::set_item(&dict, #names, #bindings)?;)*
+                dict
+            }}
+        }
+        Fields::Unnamed(_) if included.len() == 1 => {
+            let binding = &included[0].binding;
+            quote! { ::rustpy::ToPyType::to_py_object(#binding, state)? }
+        }
+        Fields::Unnamed(_) => {
+            let len = included.len();
+            let indices = 0..len;
+            let bindings = included.iter().map(|m| &m.binding);
+            quote! {{
+                let tuple = ::rustpy::derive::new_tuple(state, #len)?;
+                #(::rustpy::derive::set_tuple_item(&tuple, #indices, #bindings)?;)*
+                tuple
+            }}
+        }
+        Fields::Unit => quote! { ::rustpy::derive::none(state) },
+    }
+}
+
+/// Expression building `constructor` out of the python object `source`.
+fn fields_from_py_object(constructor: TokenStream2,
+                         fields: &Fields,
+                         members: &[Member],
+                         source: &Ident)
+                         -> TokenStream2 {
+    let included = members.iter().filter(|m| !m.attrs.skip).count();
+    let mut position = 0usize;
+    let values: Vec<TokenStream2> = members.iter()
+        .map(|m| {
+            if m.attrs.skip {
+                return quote! { ::std::default::Default::default() };
+            }
+            match *fields {
+                Fields::Named(_) => {
+                    let name = &m.name;
+                    let field = position;
+                    position += 1;
+                    match m.attrs.default {
+                        FieldDefault::None => {
+                            quote! { ::rustpy::derive::required_field(&#source, #name, #field)? }
+                        }
+                        FieldDefault::Trait => {
+                            quote! {
+                                ::rustpy::derive::optional_field(&#source, #name, #field)?
+                                    .unwrap_or_else(::std::default::Default::default)
+                            }
+                        }
+                        FieldDefault::Function(ref path) => {
+                            quote! {
+                                ::rustpy::derive::optional_field(&#source, #name, #field)?
+                                    .unwrap_or_else(#path)
+                            }
+                        }
+                    }
+                }
+                _ if included == 1 => quote! { state.from_py_object(#source)? },
+                _ => {
+                    position += 1;
+                    quote! { state.from_py_object(items.next().unwrap())? }
+                }
+            }
+        })
+        .collect();
+    let members = members.iter().map(|m| &m.member);
+    match *fields {
+        Fields::Named(_) => {
+            quote! {{
+                ::rustpy::derive::check_field_count(&#source, #included)?;
+                #constructor { #(#members: #values),* }
+            }}
+        }
+        Fields::Unnamed(_) if included <= 1 => quote! { #constructor { #(#members: #values),* } },
+        Fields::Unnamed(_) => {
+            quote! {{
+                let mut items = ::rustpy::derive::tuple_items(&#source, #position)?.into_iter();
+                #constructor { #(#members: #values),* }
+            }}
+        }
+        Fields::Unit => quote! { #constructor },
+    }
+}
+
+fn to_py_type(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_bounds(&input.generics, quote!(::rustpy::ToPyType));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let members = members(&data.fields)?;
+            let accessors = members.iter().map(|m| &m.member);
+            let bindings = members.iter().map(|m| &m.binding);
+            let value = fields_to_py_object(&data.fields, &members);
+            quote! {
+                #(let #bindings = &self.#accessors;)*
+                Ok(#value)
+            }
+        }
+        Data::Enum(ref data) => {
+            let arms = data.variants
+                           .iter()
+                           .map(|variant| {
+                               let ident = &variant.ident;
+                               let attrs = parse_attrs(&variant.attrs, false)?;
+                               let tag = attrs.rename.unwrap_or_else(|| ident.to_string());
+                               let members = members(&variant.fields)?;
+                               let accessors = members.iter().map(|m| &m.member);
+                               let bindings = members.iter().map(|m| &m.binding);
+                               let pattern = quote! {
+                                   Self::#ident { #(#accessors: ref #bindings),* }
+                               };
+                               Ok(match variant.fields {
+                                   Fields::Unit => quote! {
+                                       #pattern => ::rustpy::derive::unit_variant(state, #tag)
+                                   },
+                                   _ => {
+                                       let value = fields_to_py_object(&variant.fields, &members);
+                                       quote! {
+                                           #pattern => {
+                                               let value = #value;
+                                               ::rustpy::derive::tagged_object(state, #tag, &value)
+                                           }
+                                       }
+                                   }
+                               })
+                           })
+                           .collect::<Result<Vec<_>>>()?;
+            quote! {
+                match *self {
+                    #(#arms,)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(Span::call_site(), "unions cannot derive ToPyType"));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rustpy::ToPyType for #name #ty_generics #where_clause {
+            #[allow(unused_variables, clippy::needless_question_mark)]
+            fn to_py_object<'__py>(&'__py self, state: &'__py ::rustpy::PyState)
+                -> ::std::result::Result<::rustpy::PyObject<'__py>, ::rustpy::PyError>
+            {
+                #body
+            }
+        }
+    })
+}
+
+fn from_py_type(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let mut generics = add_bounds(&input.generics, quote!(::rustpy::FromPyType<'__py>));
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    generics.params.insert(0, parse_quote!('__py));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let py_object = Ident::new("py_object", Span::call_site());
+    let payload = Ident::new("payload", Span::call_site());
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let members = members(&data.fields)?;
+            let value = fields_from_py_object(quote!(Self), &data.fields, &members, &py_object);
+            match data.fields {
+                Fields::Unit => quote! {
+                    if py_object.is_none() {
+                        Ok(#value)
+                    } else {
+                        Err(::rustpy::PyError::FromTypeConversionError)
+                    }
+                },
+                _ => quote! { Ok(#value) },
+            }
+        }
+        Data::Enum(ref data) => {
+            let arms = data.variants
+                           .iter()
+                           .map(|variant| {
+                               let ident = &variant.ident;
+                               let attrs = parse_attrs(&variant.attrs, false)?;
+                               let tag = attrs.rename.unwrap_or_else(|| ident.to_string());
+                               let members = members(&variant.fields)?;
+                               let value = fields_from_py_object(quote!(Self::#ident),
+                                                                 &variant.fields,
+                                                                 &members,
+                                                                 &payload);
+                               Ok(match variant.fields {
+                                   Fields::Unit => quote! { (#tag, None) => Ok(#value) },
+                                   _ => quote! { (#tag, Some(#payload)) => Ok(#value) },
+                               })
+                           })
+                           .collect::<Result<Vec<_>>>()?;
+            quote! {
+                let (tag, payload) = ::rustpy::derive::variant(&py_object)?;
+                match (&*tag, payload) {
+                    #(#arms,)*
+                    _ => Err(::rustpy::PyError::FromTypeConversionError),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(Span::call_site(), "unions cannot derive FromPyType"));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rustpy::FromPyType<'__py> for #name #ty_generics #where_clause {
+            #[allow(unused_variables, clippy::needless_question_mark)]
+            fn from_py_object(state: &'__py ::rustpy::PyState,
+                              py_object: ::rustpy::PyObject<'__py>)
+                -> ::std::result::Result<Self, ::rustpy::PyError>
+            {
+                #body
+            }
+        }
+    })
+}
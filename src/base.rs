@@ -1,34 +1,323 @@
-use std::sync::{Mutex, MutexGuard};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::{Mutex, MutexGuard, Once};
 use std::ptr;
 use std::marker::PhantomData;
-use std::mem::transmute;
+use std::mem::{transmute, ManuallyDrop};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+use std::io::Write;
+use libc::{atexit, size_t};
+use std::error::Error;
 use std::fmt;
-pub use ffi::{PythonCAPI, PyObjectRaw};
+pub use ffi::{PythonCAPI, PyObjectRaw, PY_FILE_INPUT};
+use ffi::{PyGILState_STATE, PyThreadStateRaw, Unbound};
+use types::PyDowncast;
+use pickle;
+use owned;
+use output::{self, CapturedOutput};
+#[cfg(feature = "log")]
+use logging;
+use builder::PyStateBuilder;
+use primtypes::NoArgs;
+
+/// Interpreter bookkeeping shared by every thread.
+struct Lifecycle {
+    /// Number of live `PyState`s on all threads.
+    states: usize,
+    /// Finalize the interpreter once the last state is dropped.
+    finalize: bool,
+    /// Live or acquiring `PyState`s by thread.
+    holders: HashMap<ThreadId, usize>,
+}
+
+impl Lifecycle {
+    /// Whether `PyGILState_Ensure` can take the GIL without waiting on the
+    /// state of another thread, which only hands it back once dropped. Even
+    /// inside `allow_threads` a state can take the GIL back at any time.
+    fn gil_available(&self) -> bool {
+        let current = thread::current().id();
+        self.holders.keys().all(|&holder| holder == current)
+    }
+
+    /// Bookkeeping left by a thread that panicked while holding the lock.
+    /// It is only trusted again once the interpreter is shut down, as no
+    /// state can hold its GIL then.
+    fn recover(mut poisoned: MutexGuard<'_, Lifecycle>)
+               -> Result<MutexGuard<'_, Lifecycle>, PyError> {
+        if unsafe { Unbound.Py_IsInitialized() } != 0 {
+            return Err(PyError::InterpreterPoisoned);
+        }
+        poisoned.states = 0;
+        poisoned.finalize = false;
+        poisoned.holders.clear();
+        owned::invalidate();
+        LIFECYCLE.clear_poison();
+        Ok(poisoned)
+    }
+}
+
+thread_local! {
+    /// Number of sub-interpreters entered on this thread.
+    static ENTERED: Cell<usize> = const { Cell::new(0) };
+}
 
 lazy_static! {
-    static ref PY_MUTEX: Mutex<()> = Mutex::new(());
+    static ref LIFECYCLE: Mutex<Lifecycle> = Mutex::new(Lifecycle {
+        states: 0,
+        finalize: false,
+        holders: HashMap::new(),
+    });
 }
 
 /// Struct to control interaction with the python interpreter.
 ///
-/// There can only be one active PyState at a time, as on initialization
-/// a shared mutex gets locked. This allows for safe-ish execution of
-/// python at the cost of increased risk of deadlocks.
+/// A PyState holds the GIL of the thread it was created on, and so can not be
+/// sent to another thread. Each thread creates its own state, and states
+/// can be nested on one thread. While a state calls into python the
+/// interpreter hands the GIL to other threads, so python threads and other
+/// rust threads make progress.
+///
+/// The interpreter is initialized once, by the first state, and by default
+/// stays alive for the rest of the process so that later states find their
+/// modules already imported. It is shut down by `finalize`, by dropping a
+/// state marked with `finalize_on_drop`, or at process exit after
+/// `finalize_at_exit`, as soon as no state is left on any thread. A later
+/// state then starts a fresh interpreter.
+///
+/// Python objects borrow the state they come from, so an object outliving
+/// the interpreter is a compile time error:
+///
+/// ```rust,compile_fail
+/// # extern crate rustpy;
+/// # use rustpy::PyState;
+/// # fn main() {
+/// let py = PyState::new();
+/// let math = py.get_module("math").unwrap();
+/// py.finalize(); // error: cannot move out of `py` because it is borrowed
+/// math.get_func("sqrt").unwrap();
+/// # }
+/// ```
 pub struct PyState {
-    #[allow(dead_code)]
-    guard: MutexGuard<'static, ()>,
+    interpreter: Interpreter,
+    finalize_on_drop: bool,
+    // The GIL belongs to the thread that acquired it.
+    _not_send: PhantomData<*mut ()>,
 }
 
 impl PyState {
-    /// Get a new instance of the python interpreter.
+    /// Get a new instance of the python interpreter, acquiring the GIL for
+    /// the current thread.
+    ///
+    /// Waits as long as another thread holds a state. Panics if a thread
+    /// panicked while starting or shutting down the running interpreter,
+    /// `try_new` and `new_timeout` report both as errors instead.
     pub fn new() -> PyState {
+        match PyState::acquire(None, None) {
+            Ok(state) => state,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Get a new instance of the python interpreter, or
+    /// `PyError::InterpreterBusy` if another thread holds a state, including
+    /// a state inside `allow_threads`.
+    ///
+    /// States already held by the current thread never make the interpreter
+    /// busy, and python threads hand the GIL over on their own.
+    pub fn try_new() -> Result<PyState, PyError> {
+        PyState::acquire(Some(Duration::from_secs(0)), None)
+    }
+
+    /// Get a new instance of the python interpreter, waiting up to `timeout`
+    /// for states held by other threads to be dropped.
+    pub fn new_timeout(timeout: Duration) -> Result<PyState, PyError> {
+        PyState::acquire(Some(timeout), None)
+    }
+
+    /// Configure the interpreter with `builder` when initializing it.
+    pub(crate) fn acquire(timeout: Option<Duration>,
+                          builder: Option<&PyStateBuilder>)
+                          -> Result<PyState, PyError> {
+        // The GIL state API only knows about the main interpreter.
+        if ENTERED.with(Cell::get) > 0 {
+            return Err(PyError::SubInterpreterEntered);
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let default = PyStateBuilder::new();
+        // Only counted as a live state, and so dropped, once nothing can fail.
+        let mut state = ManuallyDrop::new(PyState {
+            interpreter: Interpreter::Main(0),
+            finalize_on_drop: false,
+            _not_send: PhantomData,
+        });
         unsafe {
-            let guard = PY_MUTEX.lock();
-            let state = PyState { guard: guard.unwrap() };
-            state.Py_Initialize();
-            state
+            loop {
+                {
+                    let mut lifecycle = match LIFECYCLE.lock() {
+                        Ok(lifecycle) => lifecycle,
+                        Err(poisoned) => try!(Lifecycle::recover(poisoned.into_inner())),
+                    };
+                    if state.Py_IsInitialized() == 0 {
+                        try!(builder.unwrap_or(&default).initialize(&state));
+                        state.PyEval_InitThreads();
+                        // Release the GIL taken by initialization so any thread can acquire it.
+                        state.PyEval_SaveThread();
+                    } else if builder.is_some() {
+                        return Err(PyError::AlreadyInitialized);
+                    }
+                    // Registered with the check, so other threads see the GIL as taken.
+                    if deadline.is_none() || lifecycle.gil_available() {
+                        lifecycle.states += 1;
+                        *lifecycle.holders.entry(thread::current().id()).or_insert(0) += 1;
+                        break;
+                    }
+                }
+                match deadline {
+                    Some(deadline) if Instant::now() >= deadline => {
+                        return Err(PyError::InterpreterBusy);
+                    }
+                    _ => thread::sleep(Duration::from_millis(1)),
+                }
+            }
+            state.interpreter = Interpreter::Main(state.PyGILState_Ensure());
+        }
+        let state = ManuallyDrop::into_inner(state);
+        owned::release_pending(&state);
+        Ok(state)
+    }
+
+    /// Shut down the interpreter when this state is dropped, making it the
+    /// top level owner of the interpreter.
+    pub fn finalize_on_drop(&mut self, finalize: bool) {
+        self.finalize_on_drop = finalize;
+    }
+
+    /// Shut down the interpreter once this state, and any other state alive
+    /// on this or another thread, is dropped.
+    ///
+    /// `sys.stdout` and `sys.stderr` are flushed and the `atexit` handlers
+    /// run before every python object is released.
+    pub fn finalize(mut self) {
+        self.finalize_on_drop = true;
+    }
+
+    /// Shut down the interpreter when the process exits, unless a state is
+    /// still alive on another thread at that point.
+    pub fn finalize_at_exit() {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(|| unsafe {
+            atexit(finalize_at_exit);
+        });
+    }
+
+    /// Release the GIL while `f` runs, so python threads make progress
+    /// during long rust computations or blocking I/O.
+    ///
+    /// `f` must be `Send`, which keeps it from using the state or any python
+    /// object without the GIL:
+    ///
+    /// ```rust,compile_fail
+    /// # extern crate rustpy;
+    /// # use rustpy::PyState;
+    /// # fn main() {
+    /// let py = PyState::new();
+    /// let math = py.get_module("math").unwrap();
+    /// py.allow_threads(|| math.get_func("sqrt").is_ok()); // error: not `Send`
+    /// # }
+    /// ```
+    pub fn allow_threads<T, F>(&self, f: F) -> T
+        where F: FnOnce() -> T + Send,
+              T: Send
+    {
+        let _restore = unsafe {
+            RestoreThread {
+                state: self,
+                thread_state: self.PyEval_SaveThread(),
+            }
+        };
+        f()
+    }
+
+    /// Send what python writes to `sys.stdout` and `sys.stderr` to `stdout`
+    /// and `stderr` while `f` runs. The previous streams are put back after
+    /// `f`, even when it panics, and errors of the writers are raised in
+    /// python as `IOError`.
+    ///
+    /// `sys.stdout` and `sys.stderr` are shared by every thread, so python
+    /// running on other threads meanwhile writes to these writers too, and
+    /// redirections overlapping across threads may put back each other's
+    /// streams. Redirect from one thread at a time.
+    pub fn redirect_output<T, F>(&self,
+                                 stdout: &mut (dyn Write + Send),
+                                 stderr: &mut (dyn Write + Send),
+                                 f: F)
+                                 -> Result<T, PyError>
+        where F: FnOnce() -> T
+    {
+        output::redirect(self, stdout, stderr, f)
+    }
+
+    /// Collect what python writes to `sys.stdout` and `sys.stderr` while `f`
+    /// runs, from any thread. See [`redirect_output`](#method.redirect_output).
+    ///
+    /// ```rust
+    /// # extern crate rustpy;
+    /// # use rustpy::PyState;
+    /// # fn main() {
+    /// let py = PyState::new();
+    /// let sys = py.get_module("sys").unwrap();
+    /// let (_, output) = py.capture_output(|| {
+    ///                         let stdout = sys.get_member_obj("stdout").unwrap();
+    ///                         stdout.call_func("write", ("hello",)).unwrap();
+    ///                     })
+    ///                     .unwrap();
+    /// assert_eq!(output.stdout, "hello");
+    /// # }
+    /// ```
+    pub fn capture_output<T, F>(&self, f: F) -> Result<(T, CapturedOutput), PyError>
+        where F: FnOnce() -> T
+    {
+        output::capture(self, f)
+    }
+
+    /// Send the records of python's `logging` module to the `log` crate,
+    /// enabled with the `log` feature.
+    ///
+    /// A handler on the root logger forwards each record with the logger
+    /// name as target, the formatted message including any traceback, and
+    /// the file and line of the call. Python levels map to the closest
+    /// `log::Level` below them, `CRITICAL` becoming `Error`. The root logger
+    /// level is set from `log::max_level()`, so set up the rust logger
+    /// first. Calling it again only updates that level.
+    ///
+    /// ```rust
+    /// # extern crate rustpy;
+    /// # use rustpy::PyState;
+    /// # fn main() {
+    /// let py = PyState::new();
+    /// py.forward_logging().unwrap();
+    /// let logging = py.get_module("logging").unwrap();
+    /// logging.call_func("warning", ("sent to the rust logger",)).unwrap();
+    /// # }
+    /// ```
+    #[cfg(feature = "log")]
+    pub fn forward_logging(&self) -> Result<(), PyError> {
+        logging::install(self)
+    }
+
+    fn flush_std_streams(&self) {
+        if let Ok(sys) = self.get_module("sys") {
+            for name in &["stdout", "stderr"] {
+                if let Ok(stream) = sys.get_member_obj(name) {
+                    let _ = stream.call_func("flush", NoArgs);
+                }
+            }
         }
+        let _ = self.get_result_exception();
     }
 
     /// Return the PyObject at the associated name. Will `Err` if no module found.
@@ -49,11 +338,75 @@ impl PyState {
         }
     }
 
+    /// Load a module from python source code.
+    ///
+    /// The module is registered in `sys.modules` under `module_name`, so
+    /// later calls return the already loaded module without running `source`
+    /// again.
+    pub fn module_from_source<'a>(&'a self,
+                                  module_name: &str,
+                                  source: &str)
+                                  -> Result<PyObject<'a>, PyError> {
+        unsafe {
+            // Other threads must not see the module before its source has run.
+            self._PyImport_AcquireLock();
+            let result = self.exec_module_source(module_name, source);
+            self._PyImport_ReleaseLock();
+            result
+        }
+    }
+
+    unsafe fn exec_module_source<'a>(&'a self,
+                                     module_name: &str,
+                                     source: &str)
+                                     -> Result<PyObject<'a>, PyError> {
+        let name = CString::new(module_name).unwrap();
+        let loaded = self.PyDict_GetItemString(self.PyImport_GetModuleDict(), name.as_ptr());
+        if !loaded.is_null() {
+            self.Py_IncRef(loaded);
+            return Ok(PyObject::new(self, loaded));
+        }
+
+        let filename = CString::new(format!("<{}>", module_name)).unwrap();
+        let code = self.Py_CompileString(CString::new(source).unwrap().as_ptr(),
+                                         filename.as_ptr(),
+                                         PY_FILE_INPUT);
+        try!(self.get_result_exception());
+        if code.is_null() {
+            return Err(PyError::NullPyObject);
+        }
+        let code = PyObject::new(self, code);
+        let py_module = self.PyImport_ExecCodeModule(name.as_ptr(), code.raw);
+        try!(self.get_result_exception());
+        if py_module.is_null() {
+            Err(PyError::NullPyObject)
+        } else {
+            Ok(PyObject::new(self, py_module))
+        }
+    }
+
     /// Helper function to convert `PyObject` back to rust types.
-    pub fn from_py_object<A: FromPyType>(&self, obj: PyObject) -> Result<A, PyError> {
+    pub fn from_py_object<'a, A: FromPyType<'a>>(&'a self, obj: PyObject<'a>) -> Result<A, PyError> {
         FromPyType::from_py_object(self, obj)
     }
 
+    /// Load an object pickled with `PyObject::to_pickle`.
+    ///
+    /// Unpickling can run arbitrary code, only load trusted data or use
+    /// `from_pickle_restricted`.
+    pub fn from_pickle<'a>(&'a self, data: &[u8]) -> Result<PyObject<'a>, PyError> {
+        pickle::loads(self, data)
+    }
+
+    /// Load a pickle, only allowing the classes and functions listed in
+    /// `allowed` as `"module.name"`, such as `"collections.OrderedDict"`.
+    pub fn from_pickle_restricted<'a>(&'a self,
+                                      data: &[u8],
+                                      allowed: &[&str])
+                                      -> Result<PyObject<'a>, PyError> {
+        pickle::loads_restricted(self, data, allowed)
+    }
+
     /// Low level function to check for python inturpreter errors
     pub fn get_result_exception(&self) -> Result<(), PyError> {
         unsafe {
@@ -107,6 +460,47 @@ impl<'a> PyObject<'a> {
         }
     }
 
+    /// Return the python `str()` of this object.
+    pub fn str(&self) -> Result<String, PyError> {
+        unsafe {
+            let py_str = self.state.PyObject_Str(self.raw);
+            if py_str.is_null() {
+                try!(self.state.get_result_exception());
+                Err(PyError::NullPyObject)
+            } else {
+                self.state.from_py_object::<String>(PyObject::new(self.state, py_str))
+            }
+        }
+    }
+
+    /// Get a typed handle such as `PyList` on this object, checking its type.
+    pub fn downcast<T: PyDowncast<'a>>(&self) -> Result<T, PyError> {
+        T::cast_from(self.clone())
+    }
+
+    /// Pickle this object with the given protocol, `-1` for the highest one.
+    pub fn to_pickle(&self, protocol: isize) -> Result<Vec<u8>, PyError> {
+        pickle::dumps(self, protocol)
+    }
+
+    /// Check whether this object is python's `None`.
+    pub fn is_none(&self) -> bool {
+        unsafe { self.raw == self.state.Py_None() }
+    }
+
+    /// Check whether this object is an instance of the python class `cls`.
+    pub fn is_instance(&self, cls: &PyObject) -> Result<bool, PyError> {
+        unsafe {
+            let result = self.state.PyObject_IsInstance(self.raw, cls.raw);
+            if result < 0 {
+                try!(self.state.get_result_exception());
+                Err(PyError::NullPyObject)
+            } else {
+                Ok(result > 0)
+            }
+        }
+    }
+
     /// Get PyObject corresponding to a function
     pub fn get_func(&self, string: &str) -> Result<PyObject<'a>, PyError> {
         self.get_member_obj(string)
@@ -129,10 +523,80 @@ impl<'a> PyObject<'a> {
     }
 
     /// Get member variable as native type
-    pub fn get_member<T: FromPyType>(&self, name: &str) -> Result<T, PyError> {
+    pub fn get_member<T: FromPyType<'a>>(&self, name: &str) -> Result<T, PyError> {
         self.get_member_obj(name).and_then(|x| self.state.from_py_object(x))
     }
 
+    /// Look up a field by key in a `dict`, by `position` in a plain tuple, or
+    /// by attribute on any other object, namedtuples included.
+    ///
+    /// Plain tuples are only read by position, so that fields named like
+    /// tuple methods such as `count` or `index` are not mistaken for them.
+    /// Returns `None` if the field could not be found.
+    pub fn find_field(&self,
+                      name: &str,
+                      position: Option<usize>)
+                      -> Result<Option<PyObject<'a>>, PyError> {
+        unsafe {
+            let state = self.state;
+            let key = CString::new(name).unwrap();
+            let raw = if state.PyDict_Check(self.raw) > 0 {
+                let item = state.PyDict_GetItemString(self.raw, key.as_ptr());
+                if !item.is_null() {
+                    state.Py_IncRef(item);
+                }
+                item
+            } else if self.is_plain_tuple() {
+                match position {
+                    Some(i) if i < state.PyTuple_Size(self.raw) as usize => {
+                        let item = state.PyTuple_GetItem(self.raw, i as size_t);
+                        state.Py_IncRef(item);
+                        item
+                    }
+                    _ => ptr::null_mut(),
+                }
+            } else if state.PyObject_HasAttrString(self.raw, key.as_ptr()) > 0 {
+                state.PyObject_GetAttrString(self.raw, key.as_ptr())
+            } else {
+                ptr::null_mut()
+            };
+            try!(state.get_result_exception());
+            if raw.is_null() {
+                Ok(None)
+            } else {
+                Ok(Some(PyObject::new(state, raw)))
+            }
+        }
+    }
+
+    /// Whether this is a tuple without named fields, unlike a namedtuple.
+    pub(crate) fn is_plain_tuple(&self) -> bool {
+        let fields = CString::new("_fields").unwrap();
+        unsafe {
+            self.state.PyTuple_Check(self.raw) > 0 &&
+            self.state.PyObject_HasAttrString(self.raw, fields.as_ptr()) == 0
+        }
+    }
+
+    /// Extract a field of a `dict`, an object or a namedtuple as native type.
+    ///
+    /// See [`find_field`](#method.find_field) for how the field is looked up.
+    /// Fails with `MissingField` if there is no such field and with
+    /// `InvalidField` if it does not convert to `T`.
+    pub fn get_field<T: FromPyType<'a>>(&self,
+                                    name: &str,
+                                    position: Option<usize>)
+                                    -> Result<T, PyError> {
+        match try!(self.find_field(name, position)) {
+            Some(field) => {
+                self.state
+                    .from_py_object(field)
+                    .map_err(|e| PyError::InvalidField(name.to_string(), Box::new(e)))
+            }
+            None => Err(PyError::MissingField(name.to_string())),
+        }
+    }
+
     /// Call a PyObject with the tuple provided in `args`
     pub fn call(&self, args: &PyObject) -> Result<PyObject<'a>, PyError> {
         unsafe {
@@ -149,27 +613,24 @@ impl<'a> PyObject<'a> {
     }
 
     /// Helper function to call returning type
-    pub fn call_with_ret<'b, T: FromPyType>(&'b self, args: &PyObject) -> Result<T, PyError> {
+    pub fn call_with_ret<T: FromPyType<'a>>(&self, args: &PyObject) -> Result<T, PyError> {
         self.call(args).and_then(|x| self.state.from_py_object::<T>(x))
     }
 
-    pub fn call_func<'b, I: ToPyType>(&'b self,
-                                      name: &str,
-                                      args: I)
-                                      -> Result<PyObject<'b>, PyError> {
+    pub fn call_func<I: ToPyType>(&self, name: &str, args: I) -> Result<PyObject<'a>, PyError> {
         self.get_func(name)
             .and_then(|x| args.to_py_object(self.state).and_then(|input| x.call(&input)))
     }
 
-    pub fn call_func_with_ret<I: ToPyType, R: FromPyType>(&self,
-                                                          name: &str,
-                                                          args: I)
-                                                          -> Result<R, PyError> {
+    pub fn call_func_with_ret<I: ToPyType, R: FromPyType<'a>>(&self,
+                                                              name: &str,
+                                                              args: I)
+                                                              -> Result<R, PyError> {
         self.get_func(name)
             .and_then(|x| args.to_py_object(self.state).and_then(|input| x.call_with_ret(&input)))
     }
 
-    fn get_iter<'b>(&'b self) -> Result<PyObject<'b>, PyError> {
+    fn get_iter(&self) -> Result<PyObject<'a>, PyError> {
         unsafe {
             let py_iter = self.state.PyObject_GetIter(self.raw);
             if py_iter.is_null() {
@@ -184,11 +645,171 @@ impl<'a> PyObject<'a> {
     ///
     /// Returns an error if this object does not implement the Python
     /// iterator protocol.
-    pub fn iter<'b, T: FromPyType>(&'b self) -> Result<PyIterator<'b, T>, PyError> {
+    pub fn iter<T: FromPyType<'a>>(&self) -> Result<PyIterator<'a, T>, PyError> {
         self.get_iter().and_then(|py_object| PyIterator::new(py_object))
     }
 }
 
+impl Drop for PyState {
+    fn drop(&mut self) {
+        let gil = match self.interpreter {
+            Interpreter::Main(gil) => gil,
+            // Ended by the `SubInterpreter` owning the state.
+            Interpreter::Sub => return,
+        };
+        let mut lifecycle = LIFECYCLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        lifecycle.states -= 1;
+        lifecycle.finalize |= self.finalize_on_drop;
+        if let Entry::Occupied(mut entry) = lifecycle.holders.entry(thread::current().id()) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+        unsafe {
+            if lifecycle.states == 0 && lifecycle.finalize {
+                lifecycle.finalize = false;
+                // A panic would leave the interpreter running and the lock poisoned.
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| self.flush_std_streams()));
+                // Finalizing keeps the GIL, the next initialization releases it.
+                self.Py_Finalize();
+                owned::invalidate();
+            } else {
+                self.PyGILState_Release(gil);
+            }
+        }
+    }
+}
+
+/// Interpreter a `PyState` runs python code in.
+enum Interpreter {
+    /// The main interpreter, entered with `PyGILState_Ensure`.
+    Main(PyGILState_STATE),
+    /// A sub-interpreter, entered by `SubInterpreter::enter`.
+    Sub,
+}
+
+/// Isolated python interpreter, with its own modules, `sys.modules` and
+/// `sys.path`.
+///
+/// A sub-interpreter runs on the GIL of the state it was created from and
+/// python code runs in it through `enter`. The closure passed to `enter` is
+/// `Send`, so it can not use the objects of another interpreter, and the
+/// objects it creates can not escape it:
+///
+/// ```rust,compile_fail
+/// # extern crate rustpy;
+/// # use rustpy::{PyState, SubInterpreter};
+/// # fn main() {
+/// let py = PyState::new();
+/// let sub = SubInterpreter::new(&py).unwrap();
+/// let math = sub.enter(|py| py.get_module("math").unwrap()); // error: lifetime
+/// # }
+/// ```
+///
+/// C extension modules are shared between interpreters, and python threads
+/// started in a sub-interpreter must have finished when it is dropped.
+pub struct SubInterpreter<'p> {
+    parent: &'p PyState,
+    state: PyState,
+    thread_state: *mut PyThreadStateRaw,
+}
+
+impl<'p> SubInterpreter<'p> {
+    /// Create a sub-interpreter running on the GIL held by `parent`.
+    pub fn new(parent: &'p PyState) -> Result<SubInterpreter<'p>, PyError> {
+        unsafe {
+            let previous = parent.PyThreadState_GET();
+            let thread_state = parent.Py_NewInterpreter();
+            parent.PyThreadState_Swap(previous);
+            if thread_state.is_null() {
+                return Err(PyError::NullPyObject);
+            }
+            Ok(SubInterpreter {
+                parent,
+                state: PyState {
+                    interpreter: Interpreter::Sub,
+                    finalize_on_drop: false,
+                    _not_send: PhantomData,
+                },
+                thread_state,
+            })
+        }
+    }
+
+    /// Run `f` with the state of this interpreter.
+    ///
+    /// `PyState::new` fails with `PyError::SubInterpreterEntered` until `f`
+    /// returns.
+    pub fn enter<T, F>(&self, f: F) -> T
+        where F: for<'s> FnOnce(&'s PyState) -> T + Send
+    {
+        let _leave = unsafe {
+            Leave {
+                state: self.parent,
+                previous: self.parent.PyThreadState_Swap(self.thread_state),
+            }
+        };
+        ENTERED.with(|entered| entered.set(entered.get() + 1));
+        f(&self.state)
+    }
+}
+
+impl<'p> Drop for SubInterpreter<'p> {
+    fn drop(&mut self) {
+        unsafe {
+            let previous = self.parent.PyThreadState_Swap(self.thread_state);
+            self.state.flush_std_streams();
+            self.parent.Py_EndInterpreter(self.thread_state);
+            self.parent.PyThreadState_Swap(previous);
+        }
+    }
+}
+
+/// Switch back to the previous interpreter after `SubInterpreter::enter`.
+struct Leave<'a> {
+    state: &'a PyState,
+    previous: *mut PyThreadStateRaw,
+}
+
+impl<'a> Drop for Leave<'a> {
+    fn drop(&mut self) {
+        ENTERED.with(|entered| entered.set(entered.get() - 1));
+        unsafe {
+            self.state.PyThreadState_Swap(self.previous);
+        }
+    }
+}
+
+/// Take the GIL back after `allow_threads`, even when the closure panics.
+struct RestoreThread<'a> {
+    state: &'a PyState,
+    thread_state: *mut PyThreadStateRaw,
+}
+
+impl<'a> Drop for RestoreThread<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.state.PyEval_RestoreThread(self.thread_state);
+        }
+    }
+}
+
+extern "C" fn finalize_at_exit() {
+    match LIFECYCLE.try_lock() {
+        Ok(ref lifecycle) if lifecycle.states == 0 => (),
+        _ => return,
+    }
+    // Already shut down by `finalize`.
+    if unsafe { Unbound.Py_IsInitialized() } == 0 {
+        return;
+    }
+    // Panicking would abort the exit.
+    if let Ok(state) = PyState::try_new() {
+        state.finalize();
+    }
+}
+
 impl<'a> Drop for PyObject<'a> {
     fn drop(&mut self) {
         unsafe {
@@ -233,6 +854,81 @@ pub enum PyError {
     PyException(String),
     NullPyObject,
     NotAnIterator,
+    /// A naive datetime was given where a timezone aware one is required.
+    NaiveDateTime,
+    /// A timezone aware datetime or time was given where a naive one is required.
+    AwareDateTime,
+    /// Error raised by a serde `Serialize` or `Deserialize` implementation.
+    Serde(String),
+    /// The named field was found neither by key, attribute nor position.
+    MissingField(String),
+    /// The named field was found but could not be converted.
+    InvalidField(String, Box<PyError>),
+    /// A buffer has the wrong format or layout to be read as a slice.
+    InvalidBuffer(String),
+    /// numpy could not be imported.
+    NumpyUnavailable(String),
+    /// An object could not be pickled or unpickled.
+    Pickle(String),
+    /// Another thread holds a `PyState`.
+    InterpreterBusy,
+    /// The virtualenv given to `PyStateBuilder` could not be activated.
+    Virtualenv(String),
+    /// A `PyStateBuilder` was built while the interpreter was running, or
+    /// set paths fixed by an earlier interpreter.
+    AlreadyInitialized,
+    /// `PyState::new` was called inside `SubInterpreter::enter`.
+    SubInterpreterEntered,
+    /// An `OwnedPyObject` was made or used outside of the main interpreter.
+    InvalidOwnedObject(String),
+    /// A thread panicked while starting or shutting down the interpreter.
+    InterpreterPoisoned,
+}
+
+impl fmt::Display for PyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PyError::FromTypeConversionError => write!(f, "could not convert python object"),
+            PyError::ToTypeConversionError => write!(f, "could not convert to python object"),
+            PyError::StringConversionError => write!(f, "invalid string"),
+            PyError::PyException(ref message) => write!(f, "python exception: {}", message),
+            PyError::NullPyObject => write!(f, "null python object"),
+            PyError::NotAnIterator => write!(f, "python object is not an iterator"),
+            PyError::NaiveDateTime => write!(f, "expected a timezone aware datetime"),
+            PyError::AwareDateTime => write!(f, "expected a naive datetime"),
+            PyError::Serde(ref message) => write!(f, "{}", message),
+            PyError::MissingField(ref name) => write!(f, "missing field `{}`", name),
+            PyError::InvalidField(ref name, ref error) => {
+                write!(f, "invalid field `{}`: {}", name, error)
+            }
+            PyError::InvalidBuffer(ref message) => write!(f, "invalid buffer: {}", message),
+            PyError::NumpyUnavailable(ref message) => {
+                write!(f, "numpy is not available: {}", message)
+            }
+            PyError::Pickle(ref message) => write!(f, "pickle error: {}", message),
+            PyError::InterpreterBusy => write!(f, "python interpreter is busy on another thread"),
+            PyError::AlreadyInitialized => write!(f, "python interpreter is already initialized"),
+            PyError::Virtualenv(ref message) => write!(f, "invalid virtualenv: {}", message),
+            PyError::InvalidOwnedObject(ref message) => {
+                write!(f, "invalid owned python object: {}", message)
+            }
+            PyError::SubInterpreterEntered => {
+                write!(f, "a python sub-interpreter is entered on this thread")
+            }
+            PyError::InterpreterPoisoned => {
+                write!(f, "python interpreter was poisoned by a panicking thread")
+            }
+        }
+    }
+}
+
+impl Error for PyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PyError::InvalidField(_, ref error) => Some(&**error),
+            _ => None,
+        }
+    }
 }
 
 /// Rust type that can be converted to a Python object
@@ -241,8 +937,11 @@ pub trait ToPyType {
 }
 
 /// Rust type that can be extracted from a Python object
-pub trait FromPyType {
-    fn from_py_object<'a>(state: &'a PyState, py_object: PyObject<'a>) -> Result<Self, PyError>
+///
+/// The lifetime is the one of the objects being extracted from, so that a
+/// `PyObject` can itself be extracted, on its own or inside a container.
+pub trait FromPyType<'a> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<Self, PyError>
         where Self: Sized;
 }
 
@@ -252,7 +951,7 @@ pub struct PyIterator<'a, T> {
     _phantom: PhantomData<T>,
 }
 
-impl<'a, T: FromPyType> PyIterator<'a, T> {
+impl<'a, T: FromPyType<'a>> PyIterator<'a, T> {
     /// Create Rust iterator from a Python object implementing the
     /// iterator protocol.
     pub fn new(obj: PyObject<'a>) -> Result<PyIterator<'a, T>, PyError> {
@@ -280,18 +979,28 @@ impl<'a, T: FromPyType> PyIterator<'a, T> {
     }
 }
 
-impl<'a, T: FromPyType> Iterator for PyIterator<'a, T> {
+impl<'a, T: FromPyType<'a>> Iterator for PyIterator<'a, T> {
     type Item = Result<T, PyError>;
+
+    /// Yields `Err` once if the python iterator raised an exception instead
+    /// of finishing normally.
     fn next(&mut self) -> Option<Result<T, PyError>> {
-        self.next_py_object()
-            .and_then(|py_object| Some(self.py_object.state.from_py_object(py_object)))
+        match self.next_py_object() {
+            Some(py_object) => Some(self.py_object.state.from_py_object(py_object)),
+            None => self.py_object.state.get_result_exception().err().map(Err),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::PyState;
-    use primtypes::{ToPyType, FromPyType, PyObject};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::{PyState, SubInterpreter};
+    use types::PyDict;
+    use primtypes::{ToPyType, FromPyType, PyObject, NoArgs};
     use super::PyError;
     macro_rules! try_or_panic (
       ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
@@ -455,4 +1164,306 @@ mod test {
         let count = try_or_panic!(perm.iter::<(isize, isize)>()).count();
         assert_eq!(6, count);
     }
+
+    #[test]
+    fn get_field_from_object() {
+        let py = PyState::new();
+        let urlparse = try_or_panic!(py.get_module("urlparse"));
+        let url = "http://example.com/some/path?q=1".to_string();
+        let parsed = try_or_panic!(urlparse.call_func("urlparse", (url,)));
+        let netloc: String = try_or_panic!(parsed.get_field("netloc", None));
+        assert_eq!(netloc, "example.com");
+        let query: String = try_or_panic!(parsed.get_field("query", None));
+        assert_eq!(query, "q=1");
+    }
+
+    #[test]
+    fn get_field_falls_back_to_position() {
+        let py = PyState::new();
+        let value = (1isize, "two".to_string());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let second: String = try_or_panic!(py_object.get_field("name", Some(1)));
+        assert_eq!(second, "two");
+        match py_object.get_field::<isize>("name", Some(2)) {
+            Err(PyError::MissingField(ref name)) if name == "name" => (),
+            other => panic!("expected missing field but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn get_field_reads_plain_tuples_by_position_only() {
+        let py = PyState::new();
+        let value = (3isize, 4isize);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let count: isize = try_or_panic!(py_object.get_field("count", Some(0)));
+        assert_eq!(count, 3);
+        let index: isize = try_or_panic!(py_object.get_field("index", Some(1)));
+        assert_eq!(index, 4);
+        match py_object.get_field::<isize>("count", None) {
+            Err(PyError::MissingField(ref name)) if name == "count" => (),
+            other => panic!("expected missing field but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn get_field_errors_name_the_field() {
+        let py = PyState::new();
+        let os = try_or_panic!(py.get_module("os"));
+        let stat = try_or_panic!(os.call_func("stat", ("/".to_string(),)));
+        let mode: isize = try_or_panic!(stat.get_field("st_mode", None));
+        assert!(mode > 0);
+        match stat.get_field::<isize>("st_missing", None) {
+            Err(PyError::MissingField(ref name)) if name == "st_missing" => (),
+            other => panic!("expected missing field but got {:?}", other),
+        };
+        match stat.get_field::<String>("st_mode", None) {
+            Err(e @ PyError::InvalidField(..)) => {
+                assert_eq!(e.to_string(),
+                           "invalid field `st_mode`: could not convert python object")
+            }
+            other => panic!("expected invalid field but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn nested_states_on_one_thread() {
+        let outer = PyState::new();
+        let math = try_or_panic!(outer.get_module("math"));
+        {
+            let inner = PyState::new();
+            let root: f64 = try_or_panic!(try_or_panic!(inner.get_module("math"))
+                                              .call_func_with_ret("sqrt", (9.0,)));
+            assert_eq!(root, 3.0);
+        }
+        let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (16.0,)));
+        assert_eq!(root, 4.0);
+    }
+
+    #[test]
+    fn states_on_many_threads() {
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                thread::spawn(move || {
+                    let py = PyState::new();
+                    let builtins = try_or_panic!(py.get_module("__builtin__"));
+                    let numbers = try_or_panic!(builtins.call_func("range", (i * 1000,)));
+                    let total: isize = try_or_panic!(builtins.call_func_with_ret("sum",
+                                                                                 (numbers,)));
+                    total
+                })
+            })
+            .collect();
+        let totals: Vec<isize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(totals, vec![0, 499500, 1999000, 4498500]);
+    }
+
+    #[test]
+    fn other_thread_runs_while_python_sleeps() {
+        let (started, wait_started) = mpsc::channel();
+        let sleeper = thread::spawn(move || {
+            let py = PyState::new();
+            let time = try_or_panic!(py.get_module("time"));
+            started.send(()).unwrap();
+            try_or_panic!(time.call_func("sleep", (0.5,)));
+            Instant::now()
+        });
+        wait_started.recv().unwrap();
+        let py = PyState::new();
+        let math = try_or_panic!(py.get_module("math"));
+        let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (4.0,)));
+        assert_eq!(root, 2.0);
+        let done = Instant::now();
+        drop(math);
+        drop(py);
+        assert!(done < sleeper.join().unwrap());
+    }
+
+    #[test]
+    fn python_threads_run_between_states() {
+        let source = concat!("import threading, time\n",
+                             "count = [0]\n",
+                             "running = [True]\n",
+                             "def spin():\n",
+                             "    while running[0]:\n",
+                             "        count[0] += 1\n",
+                             "        time.sleep(0.001)\n",
+                             "def start():\n",
+                             "    thread = threading.Thread(target=spin)\n",
+                             "    thread.start()\n",
+                             "    return thread\n",
+                             "def stop(thread):\n",
+                             "    running[0] = False\n",
+                             "    thread.join()\n",
+                             "    return count[0]\n");
+        let before: isize = {
+            let py = PyState::new();
+            let module = try_or_panic!(py.module_from_source("_rustpy_test_gil", source));
+            let thread = try_or_panic!(module.call_func("start", NoArgs));
+            let builtins = try_or_panic!(py.get_module("__builtin__"));
+            try_or_panic!(builtins.call_func("setattr", (module.clone(), "thread", thread)));
+            try_or_panic!(module.get_member::<Vec<isize>>("count"))[0]
+        };
+        // No state is alive, so the python thread holds the GIL as it needs.
+        thread::sleep(Duration::from_millis(100));
+        let py = PyState::new();
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_gil", source));
+        let thread = try_or_panic!(module.get_member_obj("thread"));
+        let after: isize = try_or_panic!(module.call_func_with_ret("stop", (thread,)));
+        assert!(after > before + 10, "python thread did not run: {} -> {}", before, after);
+    }
+
+    #[test]
+    fn python_threads_run_during_allow_threads() {
+        let py = PyState::new();
+        let source = concat!("import threading, time\n",
+                             "count = [0]\n",
+                             "running = [True]\n",
+                             "def spin():\n",
+                             "    while running[0]:\n",
+                             "        count[0] += 1\n",
+                             "        time.sleep(0.001)\n",
+                             "thread = threading.Thread(target=spin)\n",
+                             "thread.start()\n",
+                             "def stop():\n",
+                             "    running[0] = False\n",
+                             "    thread.join()\n",
+                             "    return count[0]\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_allow_threads", source));
+        let before = try_or_panic!(module.get_member::<Vec<isize>>("count"))[0];
+        let slept = py.allow_threads(|| {
+            thread::sleep(Duration::from_millis(100));
+            "slept"
+        });
+        assert_eq!(slept, "slept");
+        let after: isize = try_or_panic!(module.call_func_with_ret("stop", NoArgs));
+        assert!(after > before + 10, "python thread did not run: {} -> {}", before, after);
+    }
+
+    #[test]
+    fn allow_threads_restores_gil_after_panic() {
+        let py = PyState::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            py.allow_threads(|| panic!("rust panic"));
+        }));
+        assert!(result.is_err());
+        let math = try_or_panic!(py.get_module("math"));
+        let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (25.0,)));
+        assert_eq!(root, 5.0);
+    }
+
+    #[test]
+    fn try_new_while_other_thread_holds_state_should_err() {
+        let _py = PyState::new();
+        let (try_new, timeout) = thread::spawn(|| {
+                let start = Instant::now();
+                let timeout = PyState::new_timeout(Duration::from_millis(50));
+                (PyState::try_new().err(), (timeout.err(), start.elapsed()))
+            })
+            .join()
+            .unwrap();
+        match try_new {
+            Some(PyError::InterpreterBusy) => (),
+            result => panic!("expected a busy interpreter but got {:?}", result),
+        };
+        match timeout {
+            (Some(PyError::InterpreterBusy), elapsed) => {
+                assert!(elapsed >= Duration::from_millis(50))
+            }
+            result => panic!("expected a busy interpreter but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn try_new_while_other_thread_allows_threads_should_err() {
+        let py = PyState::new();
+        let busy = py.allow_threads(|| thread::spawn(|| PyState::try_new().err()).join().unwrap());
+        match busy {
+            Some(PyError::InterpreterBusy) => (),
+            result => panic!("expected a busy interpreter but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn new_timeout_waits_for_other_thread() {
+        let py = PyState::new();
+        let (sender, receiver) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            sender.send(()).unwrap();
+            let py = try_or_panic!(PyState::new_timeout(Duration::from_secs(10)));
+            let math = try_or_panic!(py.get_module("math"));
+            let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (4.0,)));
+            root
+        });
+        receiver.recv().unwrap();
+        thread::sleep(Duration::from_millis(20));
+        drop(py);
+        assert_eq!(waiter.join().unwrap(), 2.0);
+    }
+
+    fn has_module(py: &PyState, name: &str) -> bool {
+        let sys = try_or_panic!(py.get_module("sys"));
+        let modules = try_or_panic!(sys.get_member_obj("modules"));
+        try_or_panic!(try_or_panic!(modules.downcast::<PyDict>()).contains(name))
+    }
+
+    #[test]
+    fn sub_interpreters_have_own_modules() {
+        let py = PyState::new();
+        let first = try_or_panic!(SubInterpreter::new(&py));
+        let second = try_or_panic!(SubInterpreter::new(&py));
+        first.enter(|py| {
+            try_or_panic!(py.module_from_source("_rustpy_test_sub", "value = 1\n"));
+        });
+        let value: isize = first.enter(|py| {
+            let module = try_or_panic!(py.get_module("_rustpy_test_sub"));
+            try_or_panic!(module.get_member("value"))
+        });
+        assert_eq!(value, 1);
+        assert!(!second.enter(|py| has_module(py, "_rustpy_test_sub")));
+        assert!(!has_module(&py, "_rustpy_test_sub"));
+    }
+
+    #[test]
+    fn sub_interpreter_has_own_sys() {
+        let py = PyState::new();
+        let sub = try_or_panic!(SubInterpreter::new(&py));
+        sub.enter(|py| {
+            let sys = try_or_panic!(py.get_module("sys"));
+            let path = try_or_panic!(sys.get_member_obj("path"));
+            try_or_panic!(path.call_func("append", ("/rustpy/sub",)));
+        });
+        let sys = try_or_panic!(py.get_module("sys"));
+        let path: Vec<String> = try_or_panic!(sys.get_member("path"));
+        assert!(!path.contains(&"/rustpy/sub".to_string()));
+    }
+
+    #[test]
+    fn new_state_inside_sub_interpreter_should_err() {
+        let py = PyState::new();
+        let sub = try_or_panic!(SubInterpreter::new(&py));
+        match sub.enter(|_| PyState::try_new().err()) {
+            Some(PyError::SubInterpreterEntered) => (),
+            result => panic!("expected an entered sub-interpreter but got {:?}", result),
+        };
+        drop(PyState::new());
+    }
+
+    #[test]
+    fn nested_sub_interpreters() {
+        let py = PyState::new();
+        let outer = try_or_panic!(SubInterpreter::new(&py));
+        let root = outer.enter(|py| {
+            try_or_panic!(py.module_from_source("_rustpy_test_outer", "value = 1\n"));
+            let inner = try_or_panic!(SubInterpreter::new(py));
+            let root: f64 = inner.enter(|py| {
+                assert!(!has_module(py, "_rustpy_test_outer"));
+                let math = try_or_panic!(py.get_module("math"));
+                try_or_panic!(math.call_func_with_ret("sqrt", (16.0,)))
+            });
+            drop(inner);
+            assert!(has_module(py, "_rustpy_test_outer"));
+            root
+        });
+        assert_eq!(root, 4.0);
+    }
 }
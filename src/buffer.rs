@@ -0,0 +1,436 @@
+//! Bulk transfer of numeric arrays through the python buffer protocol.
+//!
+//! Rust slices wrapped in `Array` convert to an `array.array` with a single
+//! copy of their memory instead of one python object per element. Python
+//! objects exposing a buffer are read through `PyBuffer`, which hands out the
+//! memory of the object as a `&[T]` after checking its format and layout.
+//!
+//! Python 2.7 `array.array` only implements the old buffer interface, so it
+//! is read through `PyObject_AsReadBuffer` and its `typecode`. That interface
+//! does not lock the array against resizing, so its memory is copied.
+
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::slice;
+use libc::{c_int, c_long, c_short, c_void, ssize_t};
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::{PythonCAPI, PyBufferRaw, PY_BUF_RECORDS_RO};
+use primtypes::bytes_to_py_object;
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Signed,
+    Unsigned,
+    Float,
+}
+
+/// Numeric type that can be read from and written to a buffer.
+///
+/// # Safety
+///
+/// Every bit pattern of the size of the type must be a valid value.
+pub unsafe trait Element: Copy {
+    #[doc(hidden)]
+    fn kind() -> ElementKind;
+}
+
+macro_rules! element (
+  ($t:ty, $kind:ident) => (
+    unsafe impl Element for $t {
+        fn kind() -> ElementKind {
+            ElementKind::$kind
+        }
+    }
+  )
+);
+
+element!(i8, Signed);
+element!(i16, Signed);
+element!(i32, Signed);
+element!(i64, Signed);
+element!(u8, Unsigned);
+element!(u16, Unsigned);
+element!(u32, Unsigned);
+element!(u64, Unsigned);
+element!(f32, Float);
+element!(f64, Float);
+
+/// Kind of the single value described by a `struct` module format string.
+fn format_kind(format: &str) -> Option<ElementKind> {
+    let code = match format.as_bytes() {
+        [code] | [b'@', code] | [b'=', code] => *code,
+        [b'<', code] if cfg!(target_endian = "little") => *code,
+        [b'>', code] | [b'!', code] if cfg!(target_endian = "big") => *code,
+        _ => return None,
+    };
+    match code {
+        b'b' | b'h' | b'i' | b'l' | b'q' | b'n' => Some(ElementKind::Signed),
+        b'c' | b'B' | b'H' | b'I' | b'L' | b'Q' | b'N' => Some(ElementKind::Unsigned),
+        b'f' | b'd' => Some(ElementKind::Float),
+        _ => None,
+    }
+}
+
+/// Check that items of `itemsize` bytes laid out with `shape` and `strides`
+/// form one C-contiguous block.
+fn is_c_contiguous(itemsize: isize, shape: &[isize], strides: &[isize]) -> bool {
+    let mut expected = itemsize;
+    for (&len, &stride) in shape.iter().zip(strides).rev() {
+        if len > 1 && stride != expected {
+            return false;
+        }
+        expected *= len;
+    }
+    true
+}
+
+/// The `array.array` type code for `T`.
+fn typecode<T: Element>() -> Result<&'static str, PyError> {
+    let codes: &[(&'static str, usize)] = match T::kind() {
+        ElementKind::Signed => &[("b", 1),
+                                 ("h", mem::size_of::<c_short>()),
+                                 ("i", mem::size_of::<c_int>()),
+                                 ("l", mem::size_of::<c_long>())],
+        ElementKind::Unsigned => &[("B", 1),
+                                   ("H", mem::size_of::<c_short>()),
+                                   ("I", mem::size_of::<c_int>()),
+                                   ("L", mem::size_of::<c_long>())],
+        ElementKind::Float => &[("f", 4), ("d", 8)],
+    };
+    codes.iter()
+         .find(|&&(_, size)| size == mem::size_of::<T>())
+         .map(|&(code, _)| code)
+         .ok_or(PyError::ToTypeConversionError)
+}
+
+/// Slice converting to a python `array.array` of its memory, copied once to a
+/// `str` and again by `array.array`.
+///
+/// The data is copied because python may keep the array alive for longer
+/// than the slice is borrowed.
+#[derive(Debug, Clone, Copy)]
+pub struct Array<'s, T: Element + 's>(pub &'s [T]);
+
+impl<'s, T: Element> ToPyType for Array<'s, T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let code = try!(typecode::<T>());
+        let bytes = unsafe {
+            slice::from_raw_parts(self.0.as_ptr() as *const u8, mem::size_of_val(self.0))
+        };
+        let data = try!(bytes_to_py_object(state, bytes));
+        let array = try!(state.get_module("array"));
+        array.call_func("array", (code, data))
+    }
+}
+
+/// Memory exposed by a python object through the buffer protocol.
+///
+/// The object stays alive, and locked against resizing, until the
+/// `PyBuffer` is dropped. An `array.array` can not be locked, its items are
+/// copied instead.
+pub struct PyBuffer<'a> {
+    py_object: PyObject<'a>,
+    // Boxed since python may point `shape` and `strides` into the struct.
+    view: Option<Box<PyBufferRaw>>,
+    // Copy of an `array.array`, in words to be aligned for any `Element`.
+    _copy: Vec<u64>,
+    buf: *const c_void,
+    len: usize,
+    itemsize: usize,
+    format: String,
+    readonly: bool,
+    contiguous: bool,
+}
+
+impl<'a> PyBuffer<'a> {
+    /// Get the buffer of `py_object`.
+    pub fn get(py_object: &PyObject<'a>) -> Result<PyBuffer<'a>, PyError> {
+        let state = py_object.state;
+        unsafe {
+            if state.PyObject_CheckBuffer(py_object.raw) > 0 {
+                PyBuffer::get_new(py_object)
+            } else if state.PyObject_HasAttrString(py_object.raw,
+                                                   b"typecode\0".as_ptr() as *const _) > 0 {
+                PyBuffer::get_array(py_object)
+            } else {
+                Err(PyError::InvalidBuffer("object does not expose a buffer".to_string()))
+            }
+        }
+    }
+
+    unsafe fn get_new(py_object: &PyObject<'a>) -> Result<PyBuffer<'a>, PyError> {
+        let state = py_object.state;
+        let mut view: Box<PyBufferRaw> = Box::new(mem::zeroed());
+        if state.PyObject_GetBuffer(py_object.raw, &mut *view, PY_BUF_RECORDS_RO) != 0 {
+            try!(state.get_result_exception());
+            return Err(PyError::InvalidBuffer("could not get buffer".to_string()));
+        }
+        let format = if view.format.is_null() {
+            "B".to_string()
+        } else {
+            CStr::from_ptr(view.format).to_string_lossy().into_owned()
+        };
+        let ndim = view.ndim as usize;
+        let contiguous = if !view.suboffsets.is_null() {
+            false
+        } else if view.strides.is_null() || view.shape.is_null() {
+            true
+        } else {
+            is_c_contiguous(view.itemsize as isize,
+                            slice::from_raw_parts(view.shape as *const isize, ndim),
+                            slice::from_raw_parts(view.strides as *const isize, ndim))
+        };
+        Ok(PyBuffer {
+            py_object: py_object.clone(),
+            buf: view.buf,
+            len: view.len as usize,
+            itemsize: view.itemsize as usize,
+            format,
+            readonly: view.readonly != 0,
+            contiguous,
+            view: Some(view),
+            _copy: Vec::new(),
+        })
+    }
+
+    unsafe fn get_array(py_object: &PyObject<'a>) -> Result<PyBuffer<'a>, PyError> {
+        let state = py_object.state;
+        let format: String = try!(py_object.get_member("typecode"));
+        let itemsize: usize = try!(py_object.get_member("itemsize"));
+        let mut buf: *const c_void = ptr::null();
+        let mut len: ssize_t = 0;
+        if state.PyObject_AsReadBuffer(py_object.raw, &mut buf, &mut len) != 0 {
+            try!(state.get_result_exception());
+            return Err(PyError::InvalidBuffer("could not get buffer".to_string()));
+        }
+        // Python code can resize the array while the buffer is alive.
+        let len = len as usize;
+        let mut copy = vec![0u64; len.div_ceil(mem::size_of::<u64>())];
+        ptr::copy_nonoverlapping(buf as *const u8, copy.as_mut_ptr() as *mut u8, len);
+        Ok(PyBuffer {
+            py_object: py_object.clone(),
+            view: None,
+            buf: copy.as_ptr() as *const c_void,
+            _copy: copy,
+            len,
+            itemsize,
+            format,
+            readonly: false,
+            contiguous: true,
+        })
+    }
+
+    /// The object owning the memory.
+    pub fn object(&self) -> &PyObject<'a> {
+        &self.py_object
+    }
+
+    /// The `struct` module format of a single item.
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn itemsize(&self) -> usize {
+        self.itemsize
+    }
+
+    /// Number of items.
+    pub fn len(&self) -> usize {
+        self.len.checked_div(self.itemsize).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Borrow the items as a slice.
+    ///
+    /// Fails unless the buffer is contiguous, aligned for `T`, and has a
+    /// format of the same kind and size as `T`.
+    pub fn as_slice<T: Element>(&self) -> Result<&[T], PyError> {
+        if !self.contiguous {
+            return Err(PyError::InvalidBuffer("buffer is not contiguous".to_string()));
+        }
+        if format_kind(&self.format) != Some(T::kind()) || self.itemsize != mem::size_of::<T>() {
+            return Err(PyError::InvalidBuffer(format!("items of format `{}` and size {} are \
+                                                       not {}",
+                                                      self.format,
+                                                      self.itemsize,
+                                                      ::std::any::type_name::<T>())));
+        }
+        if self.len == 0 {
+            return Ok(&[]);
+        }
+        if !(self.buf as usize).is_multiple_of(mem::align_of::<T>()) {
+            return Err(PyError::InvalidBuffer("buffer is not aligned".to_string()));
+        }
+        unsafe { Ok(slice::from_raw_parts(self.buf as *const T, self.len())) }
+    }
+
+    /// Copy the items into a vector.
+    pub fn to_vec<T: Element>(&self) -> Result<Vec<T>, PyError> {
+        self.as_slice().map(|items| items.to_vec())
+    }
+}
+
+impl<'a> Drop for PyBuffer<'a> {
+    fn drop(&mut self) {
+        if let Some(ref mut view) = self.view {
+            unsafe {
+                self.py_object.state.PyBuffer_Release(&mut **view);
+            }
+        }
+    }
+}
+
+impl<'a> FromPyType<'a> for PyBuffer<'a> {
+    fn from_py_object(_: &'a PyState, py_object: PyObject<'a>) -> Result<PyBuffer<'a>, PyError> {
+        PyBuffer::get(&py_object)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, PyError, ToPyType};
+    use primtypes::NoArgs;
+    use super::{Array, PyBuffer, is_c_contiguous};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn large_slice_to_array() {
+        let py = PyState::new();
+        let values: Vec<f64> = (0..1000000).map(|i| i as f64).collect();
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let typecode: String = try_or_panic!(array.get_member("typecode"));
+        assert_eq!(&typecode, "d");
+        let length: usize = try_or_panic!(array.call_func_with_ret("__len__", NoArgs));
+        assert_eq!(length, 1000000);
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let total: f64 = try_or_panic!(builtins.call_func_with_ret("sum", (array,)));
+        assert_eq!(total, 499999500000.0);
+    }
+
+    #[test]
+    fn array_to_slice_and_back() {
+        let py = PyState::new();
+        let values = vec![1.5f64, -2.0, 3.25];
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let buffer = try_or_panic!(PyBuffer::get(&array));
+        assert_eq!(buffer.format(), "d");
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(try_or_panic!(buffer.as_slice::<f64>()), &values[..]);
+    }
+
+    #[test]
+    fn integer_arrays() {
+        let py = PyState::new();
+        let values = vec![-1i64, 0, i64::MAX];
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let buffer: PyBuffer = try_or_panic!(py.from_py_object(array));
+        assert_eq!(try_or_panic!(buffer.to_vec::<i64>()), values);
+
+        let values = vec![1u16, 2, 65535];
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let buffer: PyBuffer = try_or_panic!(py.from_py_object(array));
+        assert_eq!(try_or_panic!(buffer.to_vec::<u16>()), values);
+    }
+
+    #[test]
+    fn python_array_to_slice() {
+        let py = PyState::new();
+        let source = "import array\nvalues = array.array('f', [0.5, 1.5, 2.5])\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_buffer_array", source));
+        let values = try_or_panic!(module.get_member_obj("values"));
+        let buffer = try_or_panic!(PyBuffer::get(&values));
+        assert_eq!(try_or_panic!(buffer.as_slice::<f32>()), &[0.5f32, 1.5, 2.5]);
+    }
+
+    #[test]
+    fn resizing_array_keeps_slice_valid() {
+        let py = PyState::new();
+        let values = vec![1i32, 2, 3];
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let buffer = try_or_panic!(PyBuffer::get(&array));
+        let items = try_or_panic!(buffer.as_slice::<i32>());
+        let more: Vec<isize> = (0..100000).collect();
+        try_or_panic!(array.call_func("extend", (more,)));
+        assert_eq!(items, &[1, 2, 3]);
+        let length: usize = try_or_panic!(array.call_func_with_ret("__len__", NoArgs));
+        assert_eq!(length, 100003);
+    }
+
+    #[test]
+    fn resizing_bytearray_should_err() {
+        let py = PyState::new();
+        let text = try_or_panic!("abc".to_py_object(&py));
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let bytes = try_or_panic!(builtins.call_func("bytearray", (text,)));
+        let buffer = try_or_panic!(PyBuffer::get(&bytes));
+        let items = try_or_panic!(buffer.as_slice::<u8>());
+        assert!(bytes.call_func("extend", ("def",)).is_err());
+        assert_eq!(items, b"abc");
+        drop(buffer);
+        try_or_panic!(bytes.call_func("extend", ("def",)));
+    }
+
+    #[test]
+    fn bytearray_and_str_to_slice() {
+        let py = PyState::new();
+        let text = try_or_panic!("abc".to_py_object(&py));
+        let buffer = try_or_panic!(PyBuffer::get(&text));
+        assert!(buffer.readonly());
+        assert_eq!(try_or_panic!(buffer.as_slice::<u8>()), b"abc");
+
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let bytes = try_or_panic!(builtins.call_func("bytearray", (text,)));
+        let buffer = try_or_panic!(PyBuffer::get(&bytes));
+        assert_eq!(buffer.format(), "B");
+        assert!(!buffer.readonly());
+        assert_eq!(try_or_panic!(buffer.as_slice::<u8>()), b"abc");
+    }
+
+    #[test]
+    fn wrong_format_should_err() {
+        let py = PyState::new();
+        let values = vec![1i32, 2, 3];
+        let data = Array(&values);
+        let array = try_or_panic!(data.to_py_object(&py));
+        let buffer = try_or_panic!(PyBuffer::get(&array));
+        assert_eq!(try_or_panic!(buffer.as_slice::<i32>()), &[1, 2, 3]);
+        match buffer.as_slice::<f32>() {
+            Err(PyError::InvalidBuffer(_)) => (),
+            result => panic!("expected an invalid buffer but got {:?}", result),
+        }
+        assert!(buffer.as_slice::<u32>().is_err());
+        assert!(buffer.as_slice::<i64>().is_err());
+    }
+
+    #[test]
+    fn object_without_buffer_should_err() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(3isize.to_py_object(&py));
+        assert!(PyBuffer::get(&py_object).is_err());
+    }
+
+    #[test]
+    fn strided_layouts() {
+        assert!(is_c_contiguous(8, &[3], &[8]));
+        assert!(is_c_contiguous(4, &[2, 3], &[12, 4]));
+        assert!(is_c_contiguous(4, &[1, 3], &[100, 4]));
+        assert!(!is_c_contiguous(8, &[3], &[16]));
+        assert!(!is_c_contiguous(4, &[2, 3], &[4, 8]));
+    }
+}
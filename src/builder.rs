@@ -0,0 +1,282 @@
+//! Options applied when the interpreter is initialized.
+
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use libc::{c_char, c_int, size_t};
+use base::{PyState, PyObject, PyError};
+use ffi::PythonCAPI;
+
+/// Program name python uses when none is set.
+const DEFAULT_PROGRAM_NAME: &[u8] = b"python\0";
+
+const VIRTUALENV_HELPER: &str = "_rustpy_virtualenv";
+
+/// Activation of a virtualenv following its `bin/activate_this.py`.
+const VIRTUALENV_SOURCE: &str = r#"
+import os
+import site
+import sys
+
+
+def activate(base):
+    base = os.path.abspath(base)
+    if sys.platform == 'win32':
+        bin_dir = os.path.join(base, 'Scripts')
+        site_packages = os.path.join(base, 'Lib', 'site-packages')
+    else:
+        bin_dir = os.path.join(base, 'bin')
+        site_packages = os.path.join(base, 'lib', 'python%s' % sys.version[:3], 'site-packages')
+    if not os.path.isdir(site_packages):
+        raise ValueError('%s has no %s directory' % (base, site_packages))
+    os.environ['PATH'] = bin_dir + os.pathsep + os.environ.get('PATH', '')
+    prev_sys_path = list(sys.path)
+    site.addsitedir(site_packages)
+    sys.real_prefix = sys.prefix
+    sys.prefix = base
+    new_sys_path = [item for item in sys.path if item not in prev_sys_path]
+    for item in new_sys_path:
+        sys.path.remove(item)
+    sys.path[:0] = new_sys_path
+"#;
+
+/// Python computes its paths when first initialized and keeps them for the
+/// rest of the process.
+static PATHS_COMPUTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+fn path_to_c_string(path: &Path) -> Result<CString, PyError> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| PyError::StringConversionError)
+}
+
+#[cfg(not(unix))]
+fn path_to_c_string(path: &Path) -> Result<CString, PyError> {
+    match path.to_str() {
+        Some(string) => CString::new(string).map_err(|_| PyError::StringConversionError),
+        None => Err(PyError::StringConversionError),
+    }
+}
+
+fn activate_virtualenv(state: &PyState, dir: &Path) -> Result<(), PyError> {
+    let helper = try!(state.module_from_source(VIRTUALENV_HELPER, VIRTUALENV_SOURCE));
+    match helper.call_func("activate", (dir,)) {
+        Ok(_) => Ok(()),
+        Err(PyError::PyException(message)) => Err(PyError::Virtualenv(message)),
+        Err(error) => Err(error),
+    }
+}
+
+/// Builder for the `PyState` that starts the interpreter, configuring it
+/// like the options of the `python` command.
+///
+/// The options only apply when `build` initializes the interpreter, so it
+/// fails with `PyError::AlreadyInitialized` while another state is alive or
+/// before the interpreter was finalized. States created later with
+/// `PyState::new` share the configured interpreter. The program name and
+/// home also fix the paths python computes, so they can only be set for the
+/// first interpreter of the process.
+///
+/// ```rust
+/// # extern crate rustpy;
+/// # use rustpy::PyStateBuilder;
+/// # fn main() {
+/// let py = PyStateBuilder::new()
+///              .argv(&["plugin-host"])
+///              .prepend_path("plugins")
+///              .dont_write_bytecode(true)
+///              .build();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PyStateBuilder {
+    program_name: Option<String>,
+    python_home: Option<PathBuf>,
+    argv: Option<Vec<String>>,
+    sys_path: Vec<PathBuf>,
+    virtualenv: Option<PathBuf>,
+    optimize: u8,
+    dont_write_bytecode: bool,
+    ignore_environment: bool,
+    no_site: bool,
+    signal_handlers: bool,
+}
+
+impl Default for PyStateBuilder {
+    fn default() -> PyStateBuilder {
+        PyStateBuilder {
+            program_name: None,
+            python_home: None,
+            argv: None,
+            sys_path: Vec::new(),
+            virtualenv: None,
+            optimize: 0,
+            dont_write_bytecode: false,
+            ignore_environment: false,
+            no_site: false,
+            signal_handlers: true,
+        }
+    }
+}
+
+impl PyStateBuilder {
+    /// Builder with the defaults of `PyState::new`.
+    pub fn new() -> PyStateBuilder {
+        PyStateBuilder::default()
+    }
+
+    /// Name of the program, from which python derives `sys.executable` and
+    /// its prefix when no home is set.
+    pub fn program_name(mut self, name: &str) -> PyStateBuilder {
+        self.program_name = Some(name.to_string());
+        self
+    }
+
+    /// Directory of the standard library, like `PYTHONHOME`.
+    pub fn python_home<P: AsRef<Path>>(mut self, home: P) -> PyStateBuilder {
+        self.python_home = Some(home.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set `sys.argv`. The directory of the script is not added to
+    /// `sys.path`.
+    pub fn argv<S: AsRef<str>>(mut self, argv: &[S]) -> PyStateBuilder {
+        self.argv = Some(argv.iter().map(|arg| arg.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Add `path` to the front of `sys.path`, after the paths added before.
+    pub fn prepend_path<P: AsRef<Path>>(mut self, path: P) -> PyStateBuilder {
+        self.sys_path.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Use the packages of the virtualenv in `dir`, like running its
+    /// `bin/activate_this.py`: its `site-packages` and `.pth` files go to
+    /// the front of `sys.path`, `sys.prefix` becomes `dir` with the previous
+    /// prefix in `sys.real_prefix`, and its `bin` directory is added to
+    /// `PATH`.
+    ///
+    /// Only `site-packages` has to exist, so a directory laid out by hand works
+    /// as well as one made by the `virtualenv` tool. The interpreter is still
+    /// the embedded one, the `bin/python` of the virtualenv is not used.
+    pub fn virtualenv<P: AsRef<Path>>(mut self, dir: P) -> PyStateBuilder {
+        self.virtualenv = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Optimization level, 1 like `-O` or 2 like `-OO`.
+    pub fn optimize(mut self, level: u8) -> PyStateBuilder {
+        self.optimize = level;
+        self
+    }
+
+    /// Do not write `.pyc` files, like `-B`.
+    pub fn dont_write_bytecode(mut self, dont_write_bytecode: bool) -> PyStateBuilder {
+        self.dont_write_bytecode = dont_write_bytecode;
+        self
+    }
+
+    /// Ignore the `PYTHON*` environment variables, like `-E`.
+    pub fn ignore_environment(mut self, ignore_environment: bool) -> PyStateBuilder {
+        self.ignore_environment = ignore_environment;
+        self
+    }
+
+    /// Do not import `site`, like `-S`.
+    pub fn no_site(mut self, no_site: bool) -> PyStateBuilder {
+        self.no_site = no_site;
+        self
+    }
+
+    /// Install the python handlers of `SIGINT` and `SIGPIPE`, on by default.
+    /// Turn off to keep the signal handlers of the rust program.
+    pub fn signal_handlers(mut self, signal_handlers: bool) -> PyStateBuilder {
+        self.signal_handlers = signal_handlers;
+        self
+    }
+
+    /// Initialize the interpreter and get a state for the current thread.
+    pub fn build(self) -> Result<PyState, PyError> {
+        if let Some(ref virtualenv) = self.virtualenv {
+            if !virtualenv.is_dir() {
+                let message = format!("{} is not a directory", virtualenv.display());
+                return Err(PyError::Virtualenv(message));
+            }
+        }
+        let state = try!(PyState::acquire(None, Some(&self)));
+        if let Some(ref virtualenv) = self.virtualenv {
+            if let Err(error) = activate_virtualenv(&state, virtualenv) {
+                // Leave no interpreter half configured behind.
+                state.finalize();
+                return Err(error);
+            }
+        }
+        Ok(state)
+    }
+
+    /// Initialize the interpreter, holding its GIL on return. Only fails
+    /// before the interpreter is initialized.
+    pub(crate) unsafe fn initialize(&self, state: &PyState) -> Result<(), PyError> {
+        let sets_paths = self.program_name.is_some() || self.python_home.is_some();
+        if sets_paths && PATHS_COMPUTED.load(Ordering::SeqCst) {
+            return Err(PyError::AlreadyInitialized);
+        }
+        let program_name = match self.program_name {
+            Some(ref name) => {
+                Some(try!(CString::new(name.as_str()).map_err(|_| PyError::StringConversionError)))
+            }
+            None => None,
+        };
+        let python_home = match self.python_home {
+            Some(ref home) => Some(try!(path_to_c_string(home))),
+            None => None,
+        };
+        let argv = match self.argv {
+            Some(ref argv) => {
+                let mut args = Vec::new();
+                for arg in argv {
+                    args.push(try!(CString::new(arg.as_str())
+                                       .map_err(|_| PyError::StringConversionError)));
+                }
+                Some(args)
+            }
+            None => None,
+        };
+        let mut sys_path = Vec::new();
+        for path in &self.sys_path {
+            sys_path.push(try!(path_to_c_string(path)));
+        }
+
+        // Python keeps both pointers for the life of the process.
+        state.Py_SetProgramName(match program_name {
+            Some(name) => name.into_raw(),
+            None => DEFAULT_PROGRAM_NAME.as_ptr() as *mut c_char,
+        });
+        state.Py_SetPythonHome(match python_home {
+            Some(home) => home.into_raw(),
+            None => ptr::null_mut(),
+        });
+        state.Py_SetFlags(self.optimize as c_int,
+                          self.dont_write_bytecode as c_int,
+                          self.ignore_environment as c_int,
+                          self.no_site as c_int);
+        state.Py_InitializeEx(self.signal_handlers as c_int);
+        PATHS_COMPUTED.store(true, Ordering::SeqCst);
+
+        if let Some(argv) = argv {
+            let mut pointers: Vec<*mut c_char> = argv.iter()
+                                                     .map(|arg| arg.as_ptr() as *mut c_char)
+                                                     .collect();
+            state.PySys_SetArgvEx(pointers.len() as c_int, pointers.as_mut_ptr(), 0);
+        }
+        let py_sys_path = state.PySys_GetObject(b"path\0".as_ptr() as *const c_char);
+        for (index, path) in sys_path.iter().enumerate() {
+            let py_path = PyObject::new(state, state.PyString_FromString(path.as_ptr()));
+            state.PyList_Insert(py_sys_path, index as size_t, py_path.raw);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,504 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use primtypes::NoArgs;
+
+/// Python 2.7 ships no concrete `tzinfo`, so timezone aware values use the
+/// fixed offset class defined here.
+const HELPER_SOURCE: &str = r#"
+import datetime
+
+ZERO = datetime.timedelta(0)
+
+
+class FixedOffset(datetime.tzinfo):
+    """Timezone at a fixed offset in seconds east of UTC."""
+
+    def __init__(self, seconds):
+        self._seconds = seconds
+        self._offset = datetime.timedelta(seconds=seconds)
+
+    def utcoffset(self, dt):
+        return self._offset
+
+    def dst(self, dt):
+        return ZERO
+
+    def tzname(self, dt):
+        sign = '-' if self._seconds < 0 else '+'
+        hours, rest = divmod(abs(self._seconds), 3600)
+        return 'UTC%s%02d:%02d' % (sign, hours, rest // 60)
+
+    def __reduce__(self):
+        return (FixedOffset, (self._seconds,))
+
+    def __repr__(self):
+        return 'FixedOffset(%d)' % self._seconds
+
+
+UTC = FixedOffset(0)
+EPOCH = datetime.datetime(1970, 1, 1, tzinfo=UTC)
+
+
+def from_timestamp(seconds, microseconds):
+    return EPOCH + datetime.timedelta(seconds=seconds, microseconds=microseconds)
+
+
+def aware_datetime(year, month, day, hour, minute, second, microsecond, offset):
+    return datetime.datetime(year, month, day, hour, minute, second, microsecond,
+                             FixedOffset(offset))
+"#;
+
+fn helper_func<'a>(state: &'a PyState, name: &str) -> Result<PyObject<'a>, PyError> {
+    state.module_from_source("_rustpy_datetime", HELPER_SOURCE)
+         .and_then(|module| module.get_func(name))
+}
+
+fn datetime_class<'a>(state: &'a PyState, name: &str) -> Result<PyObject<'a>, PyError> {
+    state.get_module("datetime").and_then(|module| module.get_member_obj(name))
+}
+
+fn call<'a, A: ToPyType>(func: PyObject<'a>, args: A) -> Result<PyObject<'a>, PyError> {
+    let py_args = try!(args.to_py_object(func.state));
+    func.call(&py_args)
+}
+
+/// Check that `py_object` is an instance of `datetime.<name>`.
+///
+/// `datetime.datetime` derives from `datetime.date`, so a datetime is not
+/// accepted where a plain date is asked for.
+fn check_instance(py_object: &PyObject, name: &str) -> Result<(), PyError> {
+    let state = py_object.state;
+    let is_instance = try!(datetime_class(state, name).and_then(|c| py_object.is_instance(&c)));
+    let is_datetime = name == "date" &&
+                      try!(datetime_class(state, "datetime")
+                               .and_then(|c| py_object.is_instance(&c)));
+    if is_instance && !is_datetime {
+        Ok(())
+    } else {
+        Err(PyError::FromTypeConversionError)
+    }
+}
+
+/// Offset from UTC in seconds of a `datetime` or `time`, `None` if it is naive.
+fn utc_offset(py_object: &PyObject) -> Result<Option<i32>, PyError> {
+    let offset = try!(py_object.call_func("utcoffset", NoArgs));
+    if offset.is_none() {
+        Ok(None)
+    } else {
+        let days: i32 = try!(offset.get_member("days"));
+        let seconds: i32 = try!(offset.get_member("seconds"));
+        Ok(Some(days * 86400 + seconds))
+    }
+}
+
+/// Number of days between 1970-01-01 and the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// `std::time::Duration` converts to `datetime.timedelta`, truncated to
+/// microseconds. Negative timedeltas fail to convert back.
+impl ToPyType for Duration {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let secs = self.as_secs();
+        let args = ((secs / 86400) as i64,
+                    (secs % 86400) as i64,
+                    self.subsec_micros() as i64);
+        datetime_class(state, "timedelta").and_then(|class| call(class, args))
+    }
+}
+
+impl<'a> FromPyType<'a> for Duration {
+    fn from_py_object(_: &PyState, py_object: PyObject) -> Result<Duration, PyError> {
+        try!(check_instance(&py_object, "timedelta"));
+        let days: i64 = try!(py_object.get_member("days"));
+        let seconds: i64 = try!(py_object.get_member("seconds"));
+        let microseconds: u32 = try!(py_object.get_member("microseconds"));
+        if days < 0 {
+            Err(PyError::FromTypeConversionError)
+        } else {
+            Ok(Duration::new(days as u64 * 86400 + seconds as u64, microseconds * 1000))
+        }
+    }
+}
+
+/// `std::time::SystemTime` converts to a timezone aware `datetime.datetime`
+/// in UTC, truncated to microseconds.
+///
+/// Extraction accepts an aware datetime in any timezone and fails with
+/// `PyError::NaiveDateTime` for naive ones, whose instant is unknown.
+impl ToPyType for SystemTime {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let (seconds, microseconds) = match self.duration_since(UNIX_EPOCH) {
+            Ok(after) => (after.as_secs() as i64, after.subsec_micros() as i64),
+            Err(before) => {
+                let before = before.duration();
+                (-(before.as_secs() as i64), -(before.subsec_micros() as i64))
+            }
+        };
+        helper_func(state, "from_timestamp").and_then(|func| call(func, (seconds, microseconds)))
+    }
+}
+
+impl<'a> FromPyType<'a> for SystemTime {
+    fn from_py_object(_: &PyState, py_object: PyObject) -> Result<SystemTime, PyError> {
+        try!(check_instance(&py_object, "datetime"));
+        let offset = match try!(utc_offset(&py_object)) {
+            Some(offset) => offset as i64,
+            None => return Err(PyError::NaiveDateTime),
+        };
+        let days = days_from_civil(try!(py_object.get_member("year")),
+                                   try!(py_object.get_member("month")),
+                                   try!(py_object.get_member("day")));
+        let hour: i64 = try!(py_object.get_member("hour"));
+        let minute: i64 = try!(py_object.get_member("minute"));
+        let second: i64 = try!(py_object.get_member("second"));
+        let microsecond: u64 = try!(py_object.get_member("microsecond"));
+        let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+        let time = if seconds >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+        };
+        time.and_then(|time| time.checked_add(Duration::from_micros(microsecond)))
+            .ok_or(PyError::FromTypeConversionError)
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_types {
+    use chrono::{Datelike, Timelike, DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime,
+                 TimeDelta, TimeZone, Utc};
+    use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+    use super::{call, check_instance, datetime_class, helper_func, utc_offset};
+
+    fn time_fields(time: &NaiveTime) -> Result<(u32, u32, u32, u32), PyError> {
+        // Python has no representation for leap seconds
+        if time.nanosecond() >= 1_000_000_000 {
+            Err(PyError::ToTypeConversionError)
+        } else {
+            Ok((time.hour(), time.minute(), time.second(), time.nanosecond() / 1000))
+        }
+    }
+
+    fn naive_date_from(py_object: &PyObject) -> Result<NaiveDate, PyError> {
+        let year: i32 = try!(py_object.get_member("year"));
+        let month: u32 = try!(py_object.get_member("month"));
+        let day: u32 = try!(py_object.get_member("day"));
+        NaiveDate::from_ymd_opt(year, month, day).ok_or(PyError::FromTypeConversionError)
+    }
+
+    fn naive_time_from(py_object: &PyObject) -> Result<NaiveTime, PyError> {
+        let hour: u32 = try!(py_object.get_member("hour"));
+        let minute: u32 = try!(py_object.get_member("minute"));
+        let second: u32 = try!(py_object.get_member("second"));
+        let microsecond: u32 = try!(py_object.get_member("microsecond"));
+        NaiveTime::from_hms_micro_opt(hour, minute, second, microsecond)
+            .ok_or(PyError::FromTypeConversionError)
+    }
+
+    /// Read an aware datetime as its local wall clock time and UTC offset.
+    fn aware_datetime_from(py_object: &PyObject) -> Result<DateTime<FixedOffset>, PyError> {
+        try!(check_instance(py_object, "datetime"));
+        let offset = match try!(utc_offset(py_object)) {
+            Some(offset) => try!(FixedOffset::east_opt(offset)
+                                     .ok_or(PyError::FromTypeConversionError)),
+            None => return Err(PyError::NaiveDateTime),
+        };
+        let local = NaiveDateTime::new(try!(naive_date_from(py_object)),
+                                       try!(naive_time_from(py_object)));
+        offset.from_local_datetime(&local).single().ok_or(PyError::FromTypeConversionError)
+    }
+
+    /// `chrono::NaiveDate` converts to `datetime.date`.
+    impl ToPyType for NaiveDate {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            let args = (self.year(), self.month(), self.day());
+            datetime_class(state, "date").and_then(|class| call(class, args))
+        }
+    }
+
+    impl<'a> FromPyType<'a> for NaiveDate {
+        fn from_py_object(_: &PyState, py_object: PyObject) -> Result<NaiveDate, PyError> {
+            try!(check_instance(&py_object, "date"));
+            naive_date_from(&py_object)
+        }
+    }
+
+    /// `chrono::NaiveTime` converts to a naive `datetime.time`.
+    impl ToPyType for NaiveTime {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            let args = try!(time_fields(self));
+            datetime_class(state, "time").and_then(|class| call(class, args))
+        }
+    }
+
+    impl<'a> FromPyType<'a> for NaiveTime {
+        fn from_py_object(_: &PyState, py_object: PyObject) -> Result<NaiveTime, PyError> {
+            try!(check_instance(&py_object, "time"));
+            if try!(utc_offset(&py_object)).is_some() {
+                return Err(PyError::AwareDateTime);
+            }
+            naive_time_from(&py_object)
+        }
+    }
+
+    /// `chrono::NaiveDateTime` converts to a naive `datetime.datetime`.
+    impl ToPyType for NaiveDateTime {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            let (hour, minute, second, microsecond) = try!(time_fields(&self.time()));
+            let args = (self.year(), self.month(), self.day(), hour, minute, second, microsecond);
+            datetime_class(state, "datetime").and_then(|class| call(class, args))
+        }
+    }
+
+    impl<'a> FromPyType<'a> for NaiveDateTime {
+        fn from_py_object(_: &PyState, py_object: PyObject) -> Result<NaiveDateTime, PyError> {
+            try!(check_instance(&py_object, "datetime"));
+            if try!(utc_offset(&py_object)).is_some() {
+                return Err(PyError::AwareDateTime);
+            }
+            Ok(NaiveDateTime::new(try!(naive_date_from(&py_object)),
+                                  try!(naive_time_from(&py_object))))
+        }
+    }
+
+    fn aware_datetime_to_py_object<'a>(state: &'a PyState,
+                                       time: &DateTime<FixedOffset>)
+                                       -> Result<PyObject<'a>, PyError> {
+        let local = time.naive_local();
+        let (hour, minute, second, microsecond) = try!(time_fields(&local.time()));
+        let args = (local.year(),
+                    local.month(),
+                    local.day(),
+                    hour,
+                    minute,
+                    second,
+                    microsecond,
+                    time.offset().local_minus_utc());
+        helper_func(state, "aware_datetime").and_then(|func| call(func, args))
+    }
+
+    /// `chrono::DateTime<FixedOffset>` converts to an aware `datetime.datetime`
+    /// keeping its UTC offset.
+    impl ToPyType for DateTime<FixedOffset> {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            aware_datetime_to_py_object(state, self)
+        }
+    }
+
+    impl<'a> FromPyType<'a> for DateTime<FixedOffset> {
+        fn from_py_object(_: &PyState,
+                          py_object: PyObject)
+                          -> Result<DateTime<FixedOffset>, PyError> {
+            aware_datetime_from(&py_object)
+        }
+    }
+
+    /// `chrono::DateTime<Utc>` converts to an aware `datetime.datetime` in UTC.
+    /// Extraction accepts aware datetimes in any timezone.
+    impl ToPyType for DateTime<Utc> {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            aware_datetime_to_py_object(state, &self.fixed_offset())
+        }
+    }
+
+    impl<'a> FromPyType<'a> for DateTime<Utc> {
+        fn from_py_object(_: &PyState, py_object: PyObject) -> Result<DateTime<Utc>, PyError> {
+            aware_datetime_from(&py_object).map(|time| time.with_timezone(&Utc))
+        }
+    }
+
+    /// `chrono::TimeDelta` converts to `datetime.timedelta`, truncated to
+    /// microseconds.
+    impl ToPyType for TimeDelta {
+        fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            let days = self.num_days();
+            let rest = *self - TimeDelta::days(days);
+            let seconds = rest.num_seconds();
+            let microseconds = (rest - TimeDelta::seconds(seconds)).num_microseconds().unwrap();
+            datetime_class(state, "timedelta")
+                .and_then(|class| call(class, (days, seconds, microseconds)))
+        }
+    }
+
+    impl<'a> FromPyType<'a> for TimeDelta {
+        fn from_py_object(_: &PyState, py_object: PyObject) -> Result<TimeDelta, PyError> {
+            try!(check_instance(&py_object, "timedelta"));
+            let days: i64 = try!(py_object.get_member("days"));
+            let seconds: i64 = try!(py_object.get_member("seconds"));
+            let microseconds: i64 = try!(py_object.get_member("microseconds"));
+            Ok(TimeDelta::days(days) + TimeDelta::seconds(seconds) +
+               TimeDelta::microseconds(microseconds))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use base::{PyState, PyError, ToPyType};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn duration_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = Duration::new(3 * 86400 + 62, 5000);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{3 days, 0:01:02.000005}");
+        let returned = try_or_panic!(py.from_py_object::<Duration>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn negative_timedelta_to_duration_should_err() {
+        let py = PyState::new();
+        let datetime = try_or_panic!(py.get_module("datetime"));
+        let delta = try_or_panic!(datetime.call_func("timedelta", (-1,)));
+        match py.from_py_object::<Duration>(delta) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn system_time_to_aware_datetime() {
+        let py = PyState::new();
+        let value = UNIX_EPOCH + Duration::new(1_000_000_000, 250_000_000);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let iso: String = try_or_panic!(py_object.call_func_with_ret("isoformat", ::NoArgs));
+        assert_eq!(iso, "2001-09-09T01:46:40.250000+00:00");
+        let returned = try_or_panic!(py.from_py_object::<SystemTime>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn system_time_before_epoch_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = UNIX_EPOCH - Duration::new(86400 * 365, 500_000);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<SystemTime>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn naive_datetime_to_system_time_should_err() {
+        let py = PyState::new();
+        let datetime = try_or_panic!(py.get_module("datetime"));
+        let naive = try_or_panic!(datetime.call_func("datetime", (2015, 6, 1)));
+        match py.from_py_object::<SystemTime>(naive) {
+            Err(PyError::NaiveDateTime) => (),
+            x => panic!("should have failed with NaiveDateTime but got {:?}", x),
+        };
+    }
+
+    #[cfg(feature = "chrono")]
+    mod chrono_types {
+        use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+                     TimeZone, Utc};
+        use base::{PyState, PyError, ToPyType};
+
+        #[test]
+        fn naive_date_to_py_object_and_back() {
+            let py = PyState::new();
+            let value = NaiveDate::from_ymd_opt(2015, 6, 1).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            assert_eq!(format!("{:?}", py_object), "PyObject{2015-06-01}");
+            let returned = try_or_panic!(py.from_py_object::<NaiveDate>(py_object));
+            assert_eq!(returned, value);
+        }
+
+        #[test]
+        fn naive_time_to_py_object_and_back() {
+            let py = PyState::new();
+            let value = NaiveTime::from_hms_micro_opt(13, 5, 7, 42).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            let returned = try_or_panic!(py.from_py_object::<NaiveTime>(py_object));
+            assert_eq!(returned, value);
+        }
+
+        #[test]
+        fn naive_datetime_to_py_object_and_back() {
+            let py = PyState::new();
+            let value = NaiveDate::from_ymd_opt(2015, 6, 1)
+                            .unwrap()
+                            .and_hms_micro_opt(13, 5, 7, 42)
+                            .unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            let returned = try_or_panic!(py.from_py_object::<NaiveDateTime>(py_object));
+            assert_eq!(returned, value);
+        }
+
+        #[test]
+        fn fixed_offset_datetime_to_py_object_and_back() {
+            let py = PyState::new();
+            let offset = FixedOffset::west_opt(5 * 3600 + 1800).unwrap();
+            let value = offset.with_ymd_and_hms(2015, 6, 1, 13, 5, 7).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            let iso: String = try_or_panic!(py_object.call_func_with_ret("isoformat", ::NoArgs));
+            assert_eq!(iso, "2015-06-01T13:05:07-05:30");
+            let returned = try_or_panic!(py.from_py_object::<DateTime<FixedOffset>>(py_object));
+            assert_eq!(returned, value);
+            assert_eq!(returned.offset(), value.offset());
+        }
+
+        #[test]
+        fn aware_datetime_to_utc() {
+            let py = PyState::new();
+            let offset = FixedOffset::east_opt(3600).unwrap();
+            let value = offset.with_ymd_and_hms(2015, 6, 1, 13, 0, 0).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            let returned = try_or_panic!(py.from_py_object::<DateTime<Utc>>(py_object));
+            assert_eq!(returned, Utc.with_ymd_and_hms(2015, 6, 1, 12, 0, 0).unwrap());
+        }
+
+        #[test]
+        fn aware_datetime_to_naive_should_err() {
+            let py = PyState::new();
+            let value = Utc.with_ymd_and_hms(2015, 6, 1, 12, 0, 0).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            match py.from_py_object::<NaiveDateTime>(py_object) {
+                Err(PyError::AwareDateTime) => (),
+                x => panic!("should have failed with AwareDateTime but got {:?}", x),
+            };
+        }
+
+        #[test]
+        fn naive_datetime_to_aware_should_err() {
+            let py = PyState::new();
+            let value = NaiveDate::from_ymd_opt(2015, 6, 1).unwrap().and_hms_opt(1, 2, 3).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            match py.from_py_object::<DateTime<Utc>>(py_object) {
+                Err(PyError::NaiveDateTime) => (),
+                x => panic!("should have failed with NaiveDateTime but got {:?}", x),
+            };
+        }
+
+        #[test]
+        fn datetime_to_naive_date_should_err() {
+            let py = PyState::new();
+            let value = NaiveDate::from_ymd_opt(2015, 6, 1).unwrap().and_hms_opt(1, 2, 3).unwrap();
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            match py.from_py_object::<NaiveDate>(py_object) {
+                Err(_) => (),
+                Ok(x) => panic!("should have failed but got {:?}", x),
+            };
+        }
+
+        #[test]
+        fn negative_time_delta_to_py_object_and_back() {
+            let py = PyState::new();
+            let value = TimeDelta::seconds(-90) + TimeDelta::microseconds(7);
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            let returned = try_or_panic!(py.from_py_object::<TimeDelta>(py_object));
+            assert_eq!(returned, value);
+        }
+    }
+}
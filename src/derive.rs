@@ -0,0 +1,406 @@
+//! Support functions for the code generated by `#[derive(ToPyType, FromPyType)]`.
+//!
+//! Not meant to be called directly, the signatures may change at any time.
+
+use std::ffi::CString;
+use libc::size_t;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::PythonCAPI;
+use primtypes::{NoArgs, bytes_to_py_object, text_from_py_object};
+
+/// Python's `None`, used for unit structs.
+pub fn none<'a>(state: &'a PyState) -> PyObject<'a> {
+    unsafe {
+        let raw = state.Py_None();
+        state.Py_IncRef(raw);
+        PyObject::new(state, raw)
+    }
+}
+
+pub fn new_dict<'a>(state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+    unsafe {
+        let raw = state.PyDict_New();
+        if raw.is_null() {
+            Err(PyError::ToTypeConversionError)
+        } else {
+            Ok(PyObject::new(state, raw))
+        }
+    }
+}
+
+pub fn set_item<T: ToPyType + ?Sized>(dict: &PyObject, key: &str, value: &T) -> Result<(), PyError> {
+    let py_value = try!(value.to_py_object(dict.state));
+    set_item_object(dict, key, &py_value)
+}
+
+fn set_item_object(dict: &PyObject, key: &str, value: &PyObject) -> Result<(), PyError> {
+    unsafe {
+        let key = CString::new(key).unwrap();
+        if dict.state.PyDict_SetItemString(dict.raw, key.as_ptr(), value.raw) != 0 {
+            try!(dict.state.get_result_exception());
+            Err(PyError::ToTypeConversionError)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn new_tuple<'a>(state: &'a PyState, len: usize) -> Result<PyObject<'a>, PyError> {
+    unsafe {
+        let raw = state.PyTuple_New(len as size_t);
+        if raw.is_null() {
+            Err(PyError::ToTypeConversionError)
+        } else {
+            Ok(PyObject::new(state, raw))
+        }
+    }
+}
+
+pub fn set_tuple_item<T: ToPyType + ?Sized>(tuple: &PyObject,
+                                            index: usize,
+                                            value: &T)
+                                            -> Result<(), PyError> {
+    let py_value = try!(value.to_py_object(tuple.state));
+    unsafe {
+        tuple.state.Py_IncRef(py_value.raw);
+        tuple.state.PyTuple_SetItem(tuple.raw, index as size_t, py_value.raw);
+    }
+    Ok(())
+}
+
+/// Externally tagged enum variant: the bare name for unit variants, and a
+/// dictionary with the name as its only key otherwise.
+pub fn unit_variant<'a>(state: &'a PyState, name: &str) -> Result<PyObject<'a>, PyError> {
+    bytes_to_py_object(state, name.as_bytes())
+}
+
+pub fn tagged_object<'a>(state: &'a PyState,
+                         name: &str,
+                         value: &PyObject)
+                         -> Result<PyObject<'a>, PyError> {
+    let dict = try!(new_dict(state));
+    try!(set_item_object(&dict, name, value));
+    Ok(dict)
+}
+
+/// Field of a named struct, see `PyObject::find_field`.
+///
+/// Returns `None` if the field is missing.
+pub fn optional_field<'a, T: FromPyType<'a>>(py_object: &PyObject<'a>,
+                                         name: &str,
+                                         position: usize)
+                                         -> Result<Option<T>, PyError> {
+    match try!(py_object.find_field(name, Some(position))) {
+        Some(field) => {
+            py_object.state
+                     .from_py_object(field)
+                     .map(Some)
+                     .map_err(|e| PyError::InvalidField(name.to_string(), Box::new(e)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn required_field<'a, T: FromPyType<'a>>(py_object: &PyObject<'a>,
+                                         name: &str,
+                                         position: usize)
+                                         -> Result<T, PyError> {
+    py_object.get_field(name, Some(position))
+}
+
+/// Check that a plain tuple read as a named struct has exactly `len` items.
+pub fn check_field_count(py_object: &PyObject, len: usize) -> Result<(), PyError> {
+    if py_object.is_plain_tuple() &&
+       unsafe { py_object.state.PyTuple_Size(py_object.raw) } as usize != len {
+        return Err(PyError::FromTypeConversionError);
+    }
+    Ok(())
+}
+
+/// Items of a tuple or list which must have exactly `len` elements.
+pub fn tuple_items<'a>(py_object: &PyObject<'a>, len: usize) -> Result<Vec<PyObject<'a>>, PyError> {
+    let state = py_object.state;
+    unsafe {
+        let raw = py_object.raw;
+        let is_tuple = state.PyTuple_Check(raw) > 0;
+        if !is_tuple && state.PyList_Check(raw) == 0 {
+            return Err(PyError::FromTypeConversionError);
+        }
+        let size = if is_tuple {
+            state.PyTuple_Size(raw)
+        } else {
+            state.PyList_Size(raw)
+        };
+        if size as usize != len {
+            return Err(PyError::FromTypeConversionError);
+        }
+        (0..len)
+            .map(|i| {
+                let item = if is_tuple {
+                    state.PyTuple_GetItem(raw, i as size_t)
+                } else {
+                    state.PyList_GetItem(raw, i as size_t)
+                };
+                if item.is_null() {
+                    Err(PyError::FromTypeConversionError)
+                } else {
+                    state.Py_IncRef(item);
+                    Ok(PyObject::new(state, item))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Split an externally tagged enum value into its variant name and payload.
+pub fn variant<'a>(py_object: &PyObject<'a>) -> Result<(String, Option<PyObject<'a>>), PyError> {
+    let state = py_object.state;
+    unsafe {
+        if state.PyString_Check(py_object.raw) > 0 || state.PyUnicode_Check(py_object.raw) > 0 {
+            let name = try!(text_from_py_object(state, py_object));
+            Ok((name, None))
+        } else if state.PyDict_Check(py_object.raw) > 0 {
+            let items = try!(py_object.call_func("items", NoArgs));
+            let mut items = try!(tuple_items(&items, 1));
+            let mut entry = try!(tuple_items(&items.remove(0), 2));
+            let payload = entry.remove(1);
+            let name = try!(text_from_py_object(state, &entry.remove(0)));
+            Ok((name, Some(payload)))
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {PyState, PyError, ToPyType, FromPyType};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Point {
+        x: isize,
+        y: isize,
+    }
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Pair(String, f64);
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Counter {
+        count: isize,
+        index: isize,
+    }
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Meters(f64);
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Wrapper<T> {
+        value: T,
+    }
+
+    fn default_port() -> isize {
+        8080
+    }
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    struct Config {
+        #[rustpy(rename = "hostname")]
+        host: String,
+        #[rustpy(default = "default_port")]
+        port: isize,
+        #[rustpy(default)]
+        tags: Vec<String>,
+        #[rustpy(skip)]
+        cache: Option<isize>,
+    }
+
+    #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect(f64, f64),
+        #[rustpy(rename = "polygon")]
+        Polygon {
+            sides: isize,
+        },
+    }
+
+    fn round_trip<T>(py: &PyState, value: T) -> T
+        where T: ToPyType + for<'a> FromPyType<'a>
+    {
+        let py_object = try_or_panic!(value.to_py_object(py));
+        try_or_panic!(py.from_py_object::<T>(py_object))
+    }
+
+    #[test]
+    fn named_struct_to_dict() {
+        let py = PyState::new();
+        let value = Point { x: 1, y: -2 };
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let x: isize = try_or_panic!(py_object.call_func_with_ret("__getitem__",
+                                                                  ("x".to_string(),)));
+        assert_eq!(x, 1);
+        let returned = try_or_panic!(py.from_py_object::<Point>(py_object));
+        assert_eq!(returned, Point { x: 1, y: -2 });
+    }
+
+    #[test]
+    fn tuple_and_unit_structs() {
+        let py = PyState::new();
+        let value = Pair("a".to_string(), 0.5);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{('a', 0.5)}");
+        assert_eq!(round_trip(&py, Pair("a".to_string(), 0.5)),
+                   Pair("a".to_string(), 0.5));
+        let meters = Meters(2.5);
+        let py_object = try_or_panic!(meters.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{2.5}");
+        assert_eq!(round_trip(&py, Meters(2.5)), Meters(2.5));
+        assert_eq!(round_trip(&py, Marker), Marker);
+        assert_eq!(round_trip(&py, Wrapper { value: vec![1isize, 2] }),
+                   Wrapper { value: vec![1isize, 2] });
+    }
+
+    #[test]
+    fn field_attributes() {
+        let py = PyState::new();
+        let value = Config {
+            host: "localhost".to_string(),
+            port: 80,
+            tags: vec!["a".to_string()],
+            cache: Some(3),
+        };
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let keys: Vec<String> = try_or_panic!(py_object.call_func_with_ret("keys", ::NoArgs));
+        let mut keys = keys;
+        keys.sort();
+        assert_eq!(keys, vec!["hostname", "port", "tags"]);
+        let returned = try_or_panic!(py.from_py_object::<Config>(py_object));
+        assert_eq!(returned.cache, None);
+        assert_eq!(returned.port, 80);
+
+        let source = "config = {'hostname': 'example.com'}\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_config", source));
+        let config = try_or_panic!(module.get_member_obj("config"));
+        let returned = try_or_panic!(py.from_py_object::<Config>(config));
+        assert_eq!(returned,
+                   Config {
+                       host: "example.com".to_string(),
+                       port: 8080,
+                       tags: vec![],
+                       cache: None,
+                   });
+    }
+
+    #[test]
+    fn missing_field_should_err() {
+        let py = PyState::new();
+        let source = "config = {'port': 1}\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_missing", source));
+        let config = try_or_panic!(module.get_member_obj("config"));
+        match py.from_py_object::<Config>(config) {
+            Err(PyError::MissingField(ref name)) if name == "hostname" => (),
+            other => panic!("expected missing field but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn struct_from_object_attributes() {
+        let py = PyState::new();
+        let source = concat!("class Point(object):\n",
+                             "    def __init__(self):\n",
+                             "        self.x = 3\n",
+                             "        self.y = 4\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_point", source));
+        let point = try_or_panic!(module.call_func("Point", ::NoArgs));
+        let returned = try_or_panic!(py.from_py_object::<Point>(point));
+        assert_eq!(returned, Point { x: 3, y: 4 });
+    }
+
+    #[test]
+    fn struct_from_namedtuple_and_tuple() {
+        let py = PyState::new();
+        let source = concat!("import collections\n",
+                             "Point = collections.namedtuple('Point', ['x', 'y'])\n",
+                             "named = Point(5, 6)\n",
+                             "plain = (7, 8)\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_tuple", source));
+        let named = try_or_panic!(module.get_member_obj("named"));
+        let returned = try_or_panic!(py.from_py_object::<Point>(named));
+        assert_eq!(returned, Point { x: 5, y: 6 });
+        let plain = try_or_panic!(module.get_member_obj("plain"));
+        let returned = try_or_panic!(py.from_py_object::<Point>(plain));
+        assert_eq!(returned, Point { x: 7, y: 8 });
+    }
+
+    #[test]
+    fn plain_tuple_fields_are_read_by_position() {
+        let py = PyState::new();
+        let source = concat!("plain = (1, 2)\n",
+                             "short = (1,)\n",
+                             "long = (1, 2, 3)\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_plain", source));
+        let plain = try_or_panic!(module.get_member_obj("plain"));
+        let returned = try_or_panic!(py.from_py_object::<Counter>(plain));
+        assert_eq!(returned, Counter { count: 1, index: 2 });
+        for name in &["short", "long"] {
+            let tuple = try_or_panic!(module.get_member_obj(name));
+            match py.from_py_object::<Counter>(tuple) {
+                Err(PyError::FromTypeConversionError) => (),
+                other => panic!("expected a conversion error but got {:?}", other),
+            };
+        }
+    }
+
+    #[test]
+    fn mistyped_field_should_err() {
+        let py = PyState::new();
+        let source = "config = {'hostname': 'example.com', 'port': 'http'}\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_derive_mistyped", source));
+        let config = try_or_panic!(module.get_member_obj("config"));
+        match py.from_py_object::<Config>(config) {
+            Err(PyError::InvalidField(ref name, _)) if name == "port" => (),
+            other => panic!("expected invalid field but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn enum_is_externally_tagged() {
+        let py = PyState::new();
+        let empty = try_or_panic!(Shape::Empty.to_py_object(&py));
+        assert_eq!(format!("{:?}", empty), "PyObject{Empty}");
+        let circle = Shape::Circle(1.5);
+        let py_object = try_or_panic!(circle.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{{'Circle': 1.5}}");
+        let rect = Shape::Rect(1.0, 2.0);
+        let py_object = try_or_panic!(rect.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{{'Rect': (1.0, 2.0)}}");
+        let polygon = Shape::Polygon { sides: 5 };
+        let py_object = try_or_panic!(polygon.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{{'polygon': {'sides': 5}}}");
+
+        assert_eq!(round_trip(&py, Shape::Empty), Shape::Empty);
+        assert_eq!(round_trip(&py, Shape::Circle(1.5)), Shape::Circle(1.5));
+        assert_eq!(round_trip(&py, Shape::Rect(1.0, 2.0)), Shape::Rect(1.0, 2.0));
+        assert_eq!(round_trip(&py, Shape::Polygon { sides: 5 }),
+                   Shape::Polygon { sides: 5 });
+    }
+
+    #[test]
+    fn unknown_variant_should_err() {
+        let py = PyState::new();
+        let value = "Triangle".to_string();
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        match py.from_py_object::<Shape>(py_object) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+}
@@ -1,18 +1,120 @@
-use libc::{c_long, c_double, size_t, c_char};
+use libc::{c_int, c_long, c_double, size_t, ssize_t, c_char, c_void};
+#[cfg(feature = "serde")]
+use libc::{c_longlong, c_ulonglong};
+use std::ptr;
 use base::PyState;
 
+/// Start symbol for `Py_CompileString` when compiling a whole module.
+pub const PY_FILE_INPUT: c_int = 257;
+
 
 /// Wrapper around the PyObject pointer that the python capi uses.
 #[derive(Debug)]
 #[repr(C)]
 pub struct PyObjectRaw;
 
+/// Opaque per-thread interpreter state.
+#[derive(Debug)]
+#[repr(C)]
+pub struct PyThreadStateRaw;
+
+/// Whether `PyGILState_Ensure` found the GIL already held by the thread.
+#[allow(non_camel_case_types)]
+pub type PyGILState_STATE = c_int;
+
+/// C function callable from python, taking `self` and its arguments.
+pub type PyCFunction = unsafe extern "C" fn(*mut PyObjectRaw, *mut PyObjectRaw)
+                                            -> *mut PyObjectRaw;
+
+/// Called when a capsule is deallocated.
+#[allow(non_camel_case_types)]
+pub type PyCapsule_Destructor = unsafe extern "C" fn(*mut PyObjectRaw);
+
+/// Method taking a single argument.
+pub const METH_O: c_int = 0x0008;
+
+/// Description of a C function exposed to python.
+#[derive(Debug)]
+#[repr(C)]
+pub struct PyMethodDef {
+    pub ml_name: *const c_char,
+    pub ml_meth: PyCFunction,
+    pub ml_flags: c_int,
+    pub ml_doc: *const c_char,
+}
+
+// Method descriptions are statics only read by python.
+unsafe impl Sync for PyMethodDef {}
+
+/// Buffer flags asking for the shape, strides and format of a buffer.
+pub const PY_BUF_RECORDS_RO: c_int = 0x0004 | 0x0010 | 0x0008;
+
+/// The `Py_buffer` structure filled by `PyObject_GetBuffer`.
+#[derive(Debug)]
+#[repr(C)]
+pub struct PyBufferRaw {
+    pub buf: *mut c_void,
+    pub obj: *mut PyObjectRaw,
+    pub len: ssize_t,
+    pub itemsize: ssize_t,
+    pub readonly: c_int,
+    pub ndim: c_int,
+    pub format: *mut c_char,
+    pub shape: *mut ssize_t,
+    pub strides: *mut ssize_t,
+    pub suboffsets: *mut ssize_t,
+    pub smalltable: [ssize_t; 2],
+    pub internal: *mut c_void,
+}
+
 #[link(name = "python2.7")]
 extern "C" {
-    fn Py_Initialize();
+    fn Py_InitializeEx(initsigs: c_int);
+    fn Py_SetProgramName(name: *mut c_char);
+    fn Py_SetPythonHome(home: *mut c_char);
+    fn PySys_SetArgvEx(argc: c_int, argv: *mut *mut c_char, updatepath: c_int);
+    fn PySys_GetObject(name: *const c_char) -> *mut PyObjectRaw;
+    fn PySys_SetObject(name: *const c_char, value: *mut PyObjectRaw) -> c_int;
+    fn PyCFunction_NewEx(method: *mut PyMethodDef,
+                         slf: *mut PyObjectRaw,
+                         module: *mut PyObjectRaw)
+                         -> *mut PyObjectRaw;
+    fn PyCapsule_New(pointer: *mut c_void,
+                     name: *const c_char,
+                     destructor: Option<PyCapsule_Destructor>)
+                     -> *mut PyObjectRaw;
+    fn PyCapsule_GetPointer(capsule: *mut PyObjectRaw, name: *const c_char) -> *mut c_void;
     fn Py_Finalize();
+    fn Py_IsInitialized() -> c_int;
+
+    fn PyEval_InitThreads();
+    fn PyEval_SaveThread() -> *mut PyThreadStateRaw;
+    fn PyEval_RestoreThread(thread_state: *mut PyThreadStateRaw);
+    fn PyGILState_Ensure() -> PyGILState_STATE;
+    fn PyGILState_Release(state: PyGILState_STATE);
+    fn PyGILState_GetThisThreadState() -> *mut PyThreadStateRaw;
+    fn PyThreadState_Swap(thread_state: *mut PyThreadStateRaw) -> *mut PyThreadStateRaw;
+    fn Py_NewInterpreter() -> *mut PyThreadStateRaw;
+    fn Py_EndInterpreter(thread_state: *mut PyThreadStateRaw);
+    fn _PyImport_AcquireLock();
+    fn _PyImport_ReleaseLock() -> c_int;
 
     fn PyImport_ImportModule(name: *const c_char) -> *mut PyObjectRaw;
+    fn PyImport_ExecCodeModule(name: *const c_char, code: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyImport_GetModuleDict() -> *mut PyObjectRaw;
+    fn Py_CompileString(source: *const c_char,
+                        filename: *const c_char,
+                        start: c_int)
+                        -> *mut PyObjectRaw;
+
+    fn PyDict_New() -> *mut PyObjectRaw;
+    fn PyDict_GetItemString(dict: *mut PyObjectRaw, key: *const c_char) -> *mut PyObjectRaw;
+    fn PyDict_SetItemString(dict: *mut PyObjectRaw,
+                            key: *const c_char,
+                            item: *mut PyObjectRaw)
+                            -> c_int;
+
+    static mut _Py_NoneStruct: PyObjectRaw;
 
     fn Py_DecRef(obj: *mut PyObjectRaw);
 
@@ -20,6 +122,7 @@ extern "C" {
                            args: *mut PyObjectRaw)
                            -> *mut PyObjectRaw;
     fn PyObject_GetAttrString(object: *mut PyObjectRaw, attr: *const c_char) -> *mut PyObjectRaw;
+    fn PyObject_HasAttrString(object: *mut PyObjectRaw, attr: *const c_char) -> c_int;
     fn PyObject_Str(obj: *mut PyObjectRaw) -> *mut PyObjectRaw;
     fn PyObject_GetIter(obj: *mut PyObjectRaw) -> *mut PyObjectRaw;
 
@@ -41,6 +144,8 @@ extern "C" {
 
     fn PyString_FromString(string: *const c_char) -> *mut PyObjectRaw;
     fn PyString_AsString(obj: *mut PyObjectRaw) -> *const c_char;
+    fn PyString_FromStringAndSize(string: *const c_char, size: size_t) -> *mut PyObjectRaw;
+    fn PyString_Size(obj: *mut PyObjectRaw) -> c_long;
 
     fn Py_IncRef(obj: *mut PyObjectRaw);
 
@@ -50,8 +155,62 @@ extern "C" {
     fn PyErr_NormalizeException(ptype: *mut *mut PyObjectRaw,
                                 pvalue: *mut *mut PyObjectRaw,
                                 ptraceback: *mut *mut PyObjectRaw);
+    fn PyErr_Clear();
+    fn PyErr_SetString(exception: *mut PyObjectRaw, message: *const c_char);
 
     fn PyIter_Next(obj: *mut PyObjectRaw) -> *mut PyObjectRaw;
+
+    fn PyComplex_FromDoubles(real: c_double, imag: c_double) -> *mut PyObjectRaw;
+    fn PyComplex_RealAsDouble(obj: *mut PyObjectRaw) -> c_double;
+    fn PyComplex_ImagAsDouble(obj: *mut PyObjectRaw) -> c_double;
+
+    fn PyObject_IsInstance(obj: *mut PyObjectRaw, cls: *mut PyObjectRaw) -> c_int;
+
+    fn PySet_New(iterable: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PySet_Add(set: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int;
+    fn PyFrozenSet_New(iterable: *mut PyObjectRaw) -> *mut PyObjectRaw;
+
+    fn PyObject_SetItem(o: *mut PyObjectRaw, key: *mut PyObjectRaw, v: *mut PyObjectRaw) -> c_int;
+
+    fn PyDict_SetItem(dict: *mut PyObjectRaw, key: *mut PyObjectRaw, item: *mut PyObjectRaw)
+                      -> c_int;
+
+    #[cfg(feature = "serde")]
+    fn PyBool_FromLong(v: c_long) -> *mut PyObjectRaw;
+    #[cfg(feature = "serde")]
+    fn PyObject_IsTrue(obj: *mut PyObjectRaw) -> c_int;
+
+    #[cfg(feature = "serde")]
+    fn PyLong_FromUnsignedLongLong(v: c_ulonglong) -> *mut PyObjectRaw;
+    #[cfg(feature = "serde")]
+    fn PyLong_AsLongLong(obj: *mut PyObjectRaw) -> c_longlong;
+    #[cfg(feature = "serde")]
+    fn PyLong_AsUnsignedLongLong(obj: *mut PyObjectRaw) -> c_ulonglong;
+
+    #[cfg(any(feature = "serde", feature = "log"))]
+    fn PyErr_Occurred() -> *mut PyObjectRaw;
+
+    fn PyList_Append(list: *mut PyObjectRaw, item: *mut PyObjectRaw) -> c_int;
+    fn PyList_Insert(list: *mut PyObjectRaw, index: size_t, item: *mut PyObjectRaw) -> c_int;
+
+    fn PyDict_GetItem(dict: *mut PyObjectRaw, key: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyDict_DelItem(dict: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int;
+    fn PyDict_Contains(dict: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int;
+    fn PyDict_Size(dict: *mut PyObjectRaw) -> c_long;
+    fn PyDict_Keys(dict: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyDict_Values(dict: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyDict_Items(dict: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyDict_Update(dict: *mut PyObjectRaw, other: *mut PyObjectRaw) -> c_int;
+
+    fn PyModule_GetDict(module: *mut PyObjectRaw) -> *mut PyObjectRaw;
+    fn PyModule_GetName(module: *mut PyObjectRaw) -> *const c_char;
+
+    fn PyObject_GetBuffer(obj: *mut PyObjectRaw, view: *mut PyBufferRaw, flags: c_int) -> c_int;
+    fn PyBuffer_Release(view: *mut PyBufferRaw);
+    fn PyObject_AsReadBuffer(obj: *mut PyObjectRaw,
+                             buffer: *mut *const c_void,
+                             buffer_len: *mut ssize_t)
+                             -> c_int;
 }
 
 #[link(name = "python2.7")]
@@ -63,20 +222,160 @@ extern "C" {
     fn RPyInt_Check(obj: *mut PyObjectRaw) -> c_long;
     fn RPyString_Check(obj: *mut PyObjectRaw) -> c_long;
     fn RPyIter_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyAnySet_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyFrozenSet_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyComplex_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyUnicode_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyDict_Check(obj: *mut PyObjectRaw) -> c_long;
+    #[cfg(feature = "serde")]
+    fn RPyBool_Check(obj: *mut PyObjectRaw) -> c_long;
+    #[cfg(any(feature = "serde", feature = "log"))]
+    fn RPyLong_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyModule_Check(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyObject_CheckBuffer(obj: *mut PyObjectRaw) -> c_long;
+    fn RPyThreadState_GET() -> *mut PyThreadStateRaw;
+    fn RPyExc_TypeError() -> *mut PyObjectRaw;
+    fn RPy_SetFlags(optimize: c_int,
+                    dont_write_bytecode: c_int,
+                    ignore_environment: c_int,
+                    no_site: c_int);
 }
 
 /// Trait to allow interaction with the python interpreter.
 #[allow(bad_style)]
 pub trait PythonCAPI {
-    unsafe fn Py_Initialize(&self) {
-        Py_Initialize();
+    unsafe fn Py_InitializeEx(&self, initsigs: c_int) {
+        Py_InitializeEx(initsigs);
+    }
+    unsafe fn Py_SetProgramName(&self, name: *mut c_char) {
+        Py_SetProgramName(name)
+    }
+    unsafe fn Py_SetPythonHome(&self, home: *mut c_char) {
+        Py_SetPythonHome(home)
+    }
+    /// Set the global flags of the `-O`, `-B`, `-E` and `-S` options.
+    unsafe fn Py_SetFlags(&self,
+                          optimize: c_int,
+                          dont_write_bytecode: c_int,
+                          ignore_environment: c_int,
+                          no_site: c_int) {
+        RPy_SetFlags(optimize, dont_write_bytecode, ignore_environment, no_site)
+    }
+    unsafe fn PySys_SetArgvEx(&self, argc: c_int, argv: *mut *mut c_char, updatepath: c_int) {
+        PySys_SetArgvEx(argc, argv, updatepath)
+    }
+    unsafe fn PySys_GetObject(&self, name: *const c_char) -> *mut PyObjectRaw {
+        PySys_GetObject(name)
+    }
+    unsafe fn PySys_SetObject(&self, name: *const c_char, value: *mut PyObjectRaw) -> c_int {
+        PySys_SetObject(name, value)
+    }
+    unsafe fn PyCFunction_NewEx(&self,
+                                method: *mut PyMethodDef,
+                                slf: *mut PyObjectRaw,
+                                module: *mut PyObjectRaw)
+                                -> *mut PyObjectRaw {
+        PyCFunction_NewEx(method, slf, module)
+    }
+    unsafe fn PyCapsule_New(&self,
+                            pointer: *mut c_void,
+                            name: *const c_char,
+                            destructor: Option<PyCapsule_Destructor>)
+                            -> *mut PyObjectRaw {
+        PyCapsule_New(pointer, name, destructor)
+    }
+    unsafe fn PyCapsule_GetPointer(&self,
+                                   capsule: *mut PyObjectRaw,
+                                   name: *const c_char)
+                                   -> *mut c_void {
+        PyCapsule_GetPointer(capsule, name)
     }
     unsafe fn Py_Finalize(&self) {
         Py_Finalize();
     }
+    unsafe fn Py_IsInitialized(&self) -> c_int {
+        Py_IsInitialized()
+    }
+    unsafe fn PyEval_InitThreads(&self) {
+        PyEval_InitThreads()
+    }
+    unsafe fn PyEval_SaveThread(&self) -> *mut PyThreadStateRaw {
+        PyEval_SaveThread()
+    }
+    unsafe fn PyEval_RestoreThread(&self, thread_state: *mut PyThreadStateRaw) {
+        PyEval_RestoreThread(thread_state)
+    }
+    unsafe fn PyGILState_Ensure(&self) -> PyGILState_STATE {
+        PyGILState_Ensure()
+    }
+    unsafe fn PyGILState_Release(&self, state: PyGILState_STATE) {
+        PyGILState_Release(state)
+    }
+    unsafe fn PyGILState_GetThisThreadState(&self) -> *mut PyThreadStateRaw {
+        PyGILState_GetThisThreadState()
+    }
+    /// Thread state holding the GIL, null when the GIL is free.
+    unsafe fn PyThreadState_GET(&self) -> *mut PyThreadStateRaw {
+        RPyThreadState_GET()
+    }
+    unsafe fn PyThreadState_Swap(&self,
+                                 thread_state: *mut PyThreadStateRaw)
+                                 -> *mut PyThreadStateRaw {
+        PyThreadState_Swap(thread_state)
+    }
+    unsafe fn Py_NewInterpreter(&self) -> *mut PyThreadStateRaw {
+        Py_NewInterpreter()
+    }
+    unsafe fn Py_EndInterpreter(&self, thread_state: *mut PyThreadStateRaw) {
+        Py_EndInterpreter(thread_state)
+    }
+    unsafe fn _PyImport_AcquireLock(&self) {
+        _PyImport_AcquireLock()
+    }
+    unsafe fn _PyImport_ReleaseLock(&self) -> c_int {
+        _PyImport_ReleaseLock()
+    }
     unsafe fn PyImport_ImportModule(&self, name: *const c_char) -> *mut PyObjectRaw {
         PyImport_ImportModule(name)
     }
+    unsafe fn PyImport_ExecCodeModule(&self,
+                                      name: *const c_char,
+                                      code: *mut PyObjectRaw)
+                                      -> *mut PyObjectRaw {
+        PyImport_ExecCodeModule(name, code)
+    }
+    unsafe fn PyImport_GetModuleDict(&self) -> *mut PyObjectRaw {
+        PyImport_GetModuleDict()
+    }
+    unsafe fn Py_CompileString(&self,
+                               source: *const c_char,
+                               filename: *const c_char,
+                               start: c_int)
+                               -> *mut PyObjectRaw {
+        Py_CompileString(source, filename, start)
+    }
+    unsafe fn PyDict_New(&self) -> *mut PyObjectRaw {
+        PyDict_New()
+    }
+    unsafe fn PyDict_SetItemString(&self,
+                                   dict: *mut PyObjectRaw,
+                                   key: *const c_char,
+                                   item: *mut PyObjectRaw)
+                                   -> c_int {
+        PyDict_SetItemString(dict, key, item)
+    }
+    unsafe fn PyDict_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyDict_Check(obj)
+    }
+    unsafe fn PyDict_GetItemString(&self,
+                                   dict: *mut PyObjectRaw,
+                                   key: *const c_char)
+                                   -> *mut PyObjectRaw {
+        PyDict_GetItemString(dict, key)
+    }
+    unsafe fn Py_None(&self) -> *mut PyObjectRaw {
+        ptr::addr_of_mut!(_Py_NoneStruct)
+    }
     unsafe fn PyInt_FromLong(&self, ival: c_long) -> *mut PyObjectRaw {
         PyInt_FromLong(ival)
     }
@@ -149,12 +448,27 @@ pub trait PythonCAPI {
     unsafe fn PyString_AsString(&self, obj: *mut PyObjectRaw) -> *const c_char {
         PyString_AsString(obj)
     }
+    unsafe fn PyString_FromStringAndSize(&self,
+                                         string: *const c_char,
+                                         size: size_t)
+                                         -> *mut PyObjectRaw {
+        PyString_FromStringAndSize(string, size)
+    }
+    unsafe fn PyString_Size(&self, obj: *mut PyObjectRaw) -> c_long {
+        PyString_Size(obj)
+    }
+    unsafe fn PyUnicode_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyUnicode_Check(obj)
+    }
     unsafe fn PyObject_GetAttrString(&self,
                                      object: *mut PyObjectRaw,
                                      attr: *const c_char)
                                      -> *mut PyObjectRaw {
         PyObject_GetAttrString(object, attr)
     }
+    unsafe fn PyObject_HasAttrString(&self, object: *mut PyObjectRaw, attr: *const c_char) -> c_int {
+        PyObject_HasAttrString(object, attr)
+    }
     unsafe fn PyErr_Fetch(&self,
                           ptype: *mut *mut PyObjectRaw,
                           pvalue: *mut *mut PyObjectRaw,
@@ -167,6 +481,15 @@ pub trait PythonCAPI {
                                        ptraceback: *mut *mut PyObjectRaw) {
         PyErr_NormalizeException(ptype, pvalue, ptraceback);
     }
+    unsafe fn PyErr_Clear(&self) {
+        PyErr_Clear();
+    }
+    unsafe fn PyErr_SetString(&self, exception: *mut PyObjectRaw, message: *const c_char) {
+        PyErr_SetString(exception, message)
+    }
+    unsafe fn PyExc_TypeError(&self) -> *mut PyObjectRaw {
+        RPyExc_TypeError()
+    }
     unsafe fn PyObject_Str(&self, obj: *mut PyObjectRaw) -> *mut PyObjectRaw {
         PyObject_Str(obj)
     }
@@ -182,6 +505,154 @@ pub trait PythonCAPI {
     unsafe fn PyIter_Next(&self, obj: *mut PyObjectRaw) -> *mut PyObjectRaw {
         PyIter_Next(obj)
     }
+    unsafe fn PySet_New(&self, iterable: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PySet_New(iterable)
+    }
+    unsafe fn PySet_Add(&self, set: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int {
+        PySet_Add(set, key)
+    }
+    unsafe fn PyFrozenSet_New(&self, iterable: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PyFrozenSet_New(iterable)
+    }
+    unsafe fn PyAnySet_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyAnySet_Check(obj)
+    }
+    unsafe fn PyFrozenSet_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyFrozenSet_Check(obj)
+    }
+    unsafe fn PyComplex_FromDoubles(&self, real: c_double, imag: c_double) -> *mut PyObjectRaw {
+        PyComplex_FromDoubles(real, imag)
+    }
+    unsafe fn PyComplex_RealAsDouble(&self, obj: *mut PyObjectRaw) -> c_double {
+        PyComplex_RealAsDouble(obj)
+    }
+    unsafe fn PyComplex_ImagAsDouble(&self, obj: *mut PyObjectRaw) -> c_double {
+        PyComplex_ImagAsDouble(obj)
+    }
+    unsafe fn PyComplex_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyComplex_Check(obj)
+    }
+    unsafe fn PyObject_IsInstance(&self, obj: *mut PyObjectRaw, cls: *mut PyObjectRaw) -> c_int {
+        PyObject_IsInstance(obj, cls)
+    }
+    unsafe fn PyDict_SetItem(&self,
+                             dict: *mut PyObjectRaw,
+                             key: *mut PyObjectRaw,
+                             item: *mut PyObjectRaw)
+                             -> c_int {
+        PyDict_SetItem(dict, key, item)
+    }
+    unsafe fn PyObject_SetItem(&self,
+                               o: *mut PyObjectRaw,
+                               key: *mut PyObjectRaw,
+                               v: *mut PyObjectRaw)
+                               -> c_int {
+        PyObject_SetItem(o, key, v)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyBool_FromLong(&self, v: c_long) -> *mut PyObjectRaw {
+        PyBool_FromLong(v)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyBool_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyBool_Check(obj)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyObject_IsTrue(&self, obj: *mut PyObjectRaw) -> c_int {
+        PyObject_IsTrue(obj)
+    }
+    #[cfg(any(feature = "serde", feature = "log"))]
+    unsafe fn PyLong_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyLong_Check(obj)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyLong_FromUnsignedLongLong(&self, v: c_ulonglong) -> *mut PyObjectRaw {
+        PyLong_FromUnsignedLongLong(v)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyLong_AsLongLong(&self, obj: *mut PyObjectRaw) -> c_longlong {
+        PyLong_AsLongLong(obj)
+    }
+    #[cfg(feature = "serde")]
+    unsafe fn PyLong_AsUnsignedLongLong(&self, obj: *mut PyObjectRaw) -> c_ulonglong {
+        PyLong_AsUnsignedLongLong(obj)
+    }
+    #[cfg(any(feature = "serde", feature = "log"))]
+    unsafe fn PyErr_Occurred(&self) -> *mut PyObjectRaw {
+        PyErr_Occurred()
+    }
+    unsafe fn PyList_Append(&self, list: *mut PyObjectRaw, item: *mut PyObjectRaw) -> c_int {
+        PyList_Append(list, item)
+    }
+    unsafe fn PyList_Insert(&self,
+                            list: *mut PyObjectRaw,
+                            index: size_t,
+                            item: *mut PyObjectRaw)
+                            -> c_int {
+        PyList_Insert(list, index, item)
+    }
+    unsafe fn PyDict_GetItem(&self,
+                             dict: *mut PyObjectRaw,
+                             key: *mut PyObjectRaw)
+                             -> *mut PyObjectRaw {
+        PyDict_GetItem(dict, key)
+    }
+    unsafe fn PyDict_DelItem(&self, dict: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int {
+        PyDict_DelItem(dict, key)
+    }
+    unsafe fn PyDict_Contains(&self, dict: *mut PyObjectRaw, key: *mut PyObjectRaw) -> c_int {
+        PyDict_Contains(dict, key)
+    }
+    unsafe fn PyDict_Size(&self, dict: *mut PyObjectRaw) -> c_long {
+        PyDict_Size(dict)
+    }
+    unsafe fn PyDict_Keys(&self, dict: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PyDict_Keys(dict)
+    }
+    unsafe fn PyDict_Values(&self, dict: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PyDict_Values(dict)
+    }
+    unsafe fn PyDict_Items(&self, dict: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PyDict_Items(dict)
+    }
+    unsafe fn PyDict_Update(&self, dict: *mut PyObjectRaw, other: *mut PyObjectRaw) -> c_int {
+        PyDict_Update(dict, other)
+    }
+    unsafe fn PyModule_Check(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyModule_Check(obj)
+    }
+    unsafe fn PyModule_GetDict(&self, module: *mut PyObjectRaw) -> *mut PyObjectRaw {
+        PyModule_GetDict(module)
+    }
+    unsafe fn PyModule_GetName(&self, module: *mut PyObjectRaw) -> *const c_char {
+        PyModule_GetName(module)
+    }
+    unsafe fn PyObject_CheckBuffer(&self, obj: *mut PyObjectRaw) -> c_long {
+        RPyObject_CheckBuffer(obj)
+    }
+    unsafe fn PyObject_GetBuffer(&self,
+                                 obj: *mut PyObjectRaw,
+                                 view: *mut PyBufferRaw,
+                                 flags: c_int)
+                                 -> c_int {
+        PyObject_GetBuffer(obj, view, flags)
+    }
+    unsafe fn PyBuffer_Release(&self, view: *mut PyBufferRaw) {
+        PyBuffer_Release(view)
+    }
+    unsafe fn PyObject_AsReadBuffer(&self,
+                                    obj: *mut PyObjectRaw,
+                                    buffer: *mut *const c_void,
+                                    buffer_len: *mut ssize_t)
+                                    -> c_int {
+        PyObject_AsReadBuffer(obj, buffer, buffer_len)
+    }
 }
 
 impl PythonCAPI for PyState {}
+
+/// Python calls made without a `PyState`, from callbacks or from threads
+/// that may not hold the GIL.
+pub struct Unbound;
+
+impl PythonCAPI for Unbound {}
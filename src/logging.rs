@@ -0,0 +1,327 @@
+//! Forwarding of python `logging` records to the `log` crate, enabled with
+//! the `log` feature.
+//!
+//! Python calls the `emit` C function with each record of a handler on the
+//! root logger, and the record is logged from there like the `log` macros
+//! do.
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_long};
+use log::{self, Level, LevelFilter, Record};
+use base::{PyObject, PyState, PyError};
+use ffi::{PythonCAPI, PyObjectRaw, PyMethodDef, METH_O, Unbound};
+
+const HELPER: &str = "_rustpy_logging";
+
+const HELPER_SOURCE: &str = r#"
+import logging
+import threading
+
+_lock = threading.Lock()
+
+
+def _text(value):
+    if isinstance(value, unicode):
+        return value.encode('utf-8')
+    return str(value)
+
+
+class RustHandler(logging.Handler):
+    """Handler sending records to the rust `log` crate."""
+
+    def __init__(self, emit):
+        logging.Handler.__init__(self)
+        self._emit = emit
+
+    def emit(self, record):
+        try:
+            error = self._emit((record.levelno, _text(record.name), _text(self.format(record)),
+                                _text(record.pathname), record.lineno))
+            if error is not None:
+                raise RuntimeError(error)
+        except (KeyboardInterrupt, SystemExit):
+            raise
+        except:
+            self.handleError(record)
+
+
+def install(emit, level):
+    root = logging.getLogger()
+    with _lock:
+        if not any(isinstance(handler, RustHandler) for handler in root.handlers):
+            root.addHandler(RustHandler(emit))
+        root.setLevel(level)
+"#;
+
+static EMIT_METHOD: PyMethodDef = PyMethodDef {
+    ml_name: b"emit\0" as *const u8 as *const c_char,
+    ml_meth: emit_callback,
+    ml_flags: METH_O,
+    ml_doc: ptr::null(),
+};
+
+/// `log` level of a python level, custom levels going to the closest level
+/// below them.
+fn log_level(levelno: c_long) -> Level {
+    match levelno {
+        levelno if levelno >= 40 => Level::Error,
+        levelno if levelno >= 30 => Level::Warn,
+        levelno if levelno >= 20 => Level::Info,
+        levelno if levelno >= 10 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+/// Lowest python level forwarded to a `log` filter.
+fn python_level(filter: LevelFilter) -> c_long {
+    match filter {
+        LevelFilter::Off => 51,
+        LevelFilter::Error => 40,
+        LevelFilter::Warn => 30,
+        LevelFilter::Info => 20,
+        LevelFilter::Debug => 10,
+        LevelFilter::Trace => 1,
+    }
+}
+
+/// Raise a `TypeError` with `message`, a nul terminated string.
+unsafe fn type_error(capi: &Unbound, message: &[u8]) -> *mut PyObjectRaw {
+    capi.PyErr_SetString(capi.PyExc_TypeError(), message.as_ptr() as *const c_char);
+    ptr::null_mut()
+}
+
+unsafe fn text(capi: &Unbound, py_string: *mut PyObjectRaw) -> String {
+    let bytes = slice::from_raw_parts(capi.PyString_AsString(py_string) as *const u8,
+                                      capi.PyString_Size(py_string) as usize);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Whether `record` is a `(int, str, str, str, int)` tuple.
+unsafe fn is_record(capi: &Unbound, record: *mut PyObjectRaw) -> bool {
+    if capi.PyTuple_Check(record) == 0 || capi.PyTuple_Size(record) != 5 {
+        return false;
+    }
+    (0..5).all(|index| {
+        let item = capi.PyTuple_GetItem(record, index);
+        match index {
+            0 | 4 => capi.PyInt_Check(item) != 0 || capi.PyLong_Check(item) != 0,
+            _ => capi.PyString_Check(item) != 0,
+        }
+    })
+}
+
+/// Log a `(levelno, name, message, pathname, lineno)` tuple. Returns `None`,
+/// or the error message as a `str`.
+unsafe extern "C" fn emit_callback(_: *mut PyObjectRaw,
+                                   record: *mut PyObjectRaw)
+                                   -> *mut PyObjectRaw {
+    let capi = Unbound;
+    if !is_record(&capi, record) {
+        return type_error(&capi,
+                          b"expected a (levelno, name, message, pathname, lineno) tuple\0");
+    }
+    let levelno = capi.PyInt_AsLong(capi.PyTuple_GetItem(record, 0));
+    let line = capi.PyInt_AsLong(capi.PyTuple_GetItem(record, 4));
+    if !capi.PyErr_Occurred().is_null() {
+        // Out of range `long`.
+        return ptr::null_mut();
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let level = log_level(levelno);
+        if level > log::max_level() {
+            return;
+        }
+        let target = text(&capi, capi.PyTuple_GetItem(record, 1));
+        let message = text(&capi, capi.PyTuple_GetItem(record, 2));
+        let file = text(&capi, capi.PyTuple_GetItem(record, 3));
+        log::logger().log(&Record::builder()
+                               .args(format_args!("{}", message))
+                               .level(level)
+                               .target(&target)
+                               .file(Some(&file))
+                               .line(u32::try_from(line).ok())
+                               .build());
+    }));
+    if result.is_err() {
+        let message = CString::new("rust logger panicked").unwrap();
+        return capi.PyString_FromString(message.as_ptr());
+    }
+    let none = capi.Py_None();
+    capi.Py_IncRef(none);
+    none
+}
+
+pub fn install(state: &PyState) -> Result<(), PyError> {
+    let helper = try!(state.module_from_source(HELPER, HELPER_SOURCE));
+    let emit = unsafe {
+        let method = &EMIT_METHOD as *const PyMethodDef as *mut PyMethodDef;
+        let emit = state.PyCFunction_NewEx(method, ptr::null_mut(), ptr::null_mut());
+        try!(state.get_result_exception());
+        PyObject::new(state, emit)
+    };
+    let level = python_level(log::max_level()) as isize;
+    try!(helper.call_func("install", (emit, level)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Mutex, Once};
+    use log::{self, Level, Log, Metadata, Record, LevelFilter};
+    use base::PyState;
+    use primtypes::NoArgs;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Logged {
+        level: Level,
+        target: String,
+        message: String,
+        file: Option<String>,
+        line: Option<u32>,
+    }
+
+    struct TestLogger {
+        records: Mutex<Vec<Logged>>,
+    }
+
+    impl Log for TestLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.records.lock().unwrap().push(Logged {
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+                file: record.file().map(str::to_string),
+                line: record.line(),
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger { records: Mutex::new(Vec::new()) };
+
+    fn set_logger() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(LevelFilter::Trace);
+        });
+    }
+
+    /// Forward the python records to the test logger and run `source` as a
+    /// module, returning the records of the loggers under `target`.
+    fn forwarded(module_name: &str, source: &str, target: &str) -> Vec<Logged> {
+        set_logger();
+        let py = PyState::new();
+        try_or_panic!(py.forward_logging());
+        let module = try_or_panic!(py.module_from_source(module_name, source));
+        try_or_panic!(module.call_func("run", NoArgs));
+        let records = LOGGER.records.lock().unwrap();
+        records.iter().filter(|record| record.target.starts_with(target)).cloned().collect()
+    }
+
+    #[test]
+    fn levels_are_mapped() {
+        let source = concat!("import logging\n",
+                             "def run():\n",
+                             "    logger = logging.getLogger('rustpy_test.levels')\n",
+                             "    logger.log(5, 'trace')\n",
+                             "    logger.debug('debug')\n",
+                             "    logger.info('info %d', 1)\n",
+                             "    logger.warning('warning')\n",
+                             "    logger.error('error')\n",
+                             "    logger.critical('critical')\n");
+        let records = forwarded("_rustpy_test_logging_levels", source, "rustpy_test.levels");
+        let levels: Vec<(Level, &str)> = records.iter()
+                                                .map(|record| (record.level, &record.message[..]))
+                                                .collect();
+        assert_eq!(levels,
+                   vec![(Level::Trace, "trace"),
+                        (Level::Debug, "debug"),
+                        (Level::Info, "info 1"),
+                        (Level::Warn, "warning"),
+                        (Level::Error, "error"),
+                        (Level::Error, "critical")]);
+    }
+
+    #[test]
+    fn location_and_logger_name() {
+        let source = concat!("import logging\n",
+                             "def run():\n",
+                             "    logger = logging.getLogger('rustpy_test.location.child')\n",
+                             "    logger.warning(u'caf\\xe9')\n");
+        let records = forwarded("_rustpy_test_logging_location", source, "rustpy_test.location");
+        assert_eq!(records,
+                   vec![Logged {
+                            level: Level::Warn,
+                            target: "rustpy_test.location.child".to_string(),
+                            message: "café".to_string(),
+                            file: Some("<_rustpy_test_logging_location>".to_string()),
+                            line: Some(4),
+                        }]);
+    }
+
+    #[test]
+    fn exceptions_include_traceback() {
+        let source = concat!("import logging\n",
+                             "def run():\n",
+                             "    try:\n",
+                             "        raise ValueError('bad value')\n",
+                             "    except ValueError:\n",
+                             "        logger = logging.getLogger('rustpy_test.exception')\n",
+                             "        logger.exception('failed')\n");
+        let records = forwarded("_rustpy_test_logging_exception", source, "rustpy_test.exception");
+        assert_eq!(records.len(), 1);
+        let message = &records[0].message;
+        assert!(message.starts_with("failed\nTraceback"), "{}", message);
+        assert!(message.contains("ValueError: bad value"), "{}", message);
+    }
+
+    #[test]
+    fn malformed_records_raise_type_error() {
+        let source = concat!("import logging\n",
+                             "def run():\n",
+                             "    handler = [handler for handler in logging.getLogger().handlers\n",
+                             "               if type(handler).__name__ == 'RustHandler'][0]\n",
+                             "    errors = 0\n",
+                             "    records = [None, (10, 'a', 'b', 'c'), (10, 'a', 1, 'c', 2),\n",
+                             "               ('10', 'a', 'b', 'c', 2), [10, 'a', 'b', 'c', 2]]\n",
+                             "    for record in records:\n",
+                             "        try:\n",
+                             "            handler._emit(record)\n",
+                             "        except TypeError:\n",
+                             "            errors += 1\n",
+                             "    return errors\n");
+        set_logger();
+        let py = PyState::new();
+        try_or_panic!(py.forward_logging());
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_logging_malformed", source));
+        let errors: isize = try_or_panic!(module.call_func_with_ret("run", NoArgs));
+        assert_eq!(errors, 5);
+    }
+
+    #[test]
+    fn installing_twice_forwards_once() {
+        let source = concat!("import logging\n",
+                             "def run():\n",
+                             "    logging.getLogger('rustpy_test.twice').info('once')\n");
+        set_logger();
+        {
+            let py = PyState::new();
+            try_or_panic!(py.forward_logging());
+        }
+        let records = forwarded("_rustpy_test_logging_twice", source, "rustpy_test.twice");
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -27,3 +27,54 @@ int RPyString_Check(PyObject* obj) {
 int RPyIter_Check(PyObject* obj) {
   return PyIter_Check(obj);
 }
+
+int RPyAnySet_Check(PyObject* obj) {
+  return PyAnySet_Check(obj);
+}
+
+int RPyFrozenSet_Check(PyObject* obj) {
+  return PyFrozenSet_Check(obj);
+}
+
+int RPyComplex_Check(PyObject* obj) {
+  return PyComplex_Check(obj);
+}
+
+int RPyUnicode_Check(PyObject* obj) {
+  return PyUnicode_Check(obj);
+}
+
+int RPyDict_Check(PyObject* obj) {
+  return PyDict_Check(obj);
+}
+
+int RPyBool_Check(PyObject* obj) {
+  return PyBool_Check(obj);
+}
+
+int RPyLong_Check(PyObject* obj) {
+  return PyLong_Check(obj);
+}
+
+int RPyModule_Check(PyObject* obj) {
+  return PyModule_Check(obj);
+}
+
+int RPyObject_CheckBuffer(PyObject* obj) {
+  return PyObject_CheckBuffer(obj);
+}
+
+PyThreadState* RPyThreadState_GET(void) {
+  return PyThreadState_GET();
+}
+
+PyObject* RPyExc_TypeError(void) {
+  return PyExc_TypeError;
+}
+
+void RPy_SetFlags(int optimize, int dont_write_bytecode, int ignore_environment, int no_site) {
+  Py_OptimizeFlag = optimize;
+  Py_DontWriteBytecodeFlag = dont_write_bytecode;
+  Py_IgnoreEnvironmentFlag = ignore_environment;
+  Py_NoSiteFlag = no_site;
+}
@@ -0,0 +1,210 @@
+use std::ffi::CString;
+#[cfg(feature = "indexmap")]
+use std::hash::{BuildHasher, Hash};
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::PythonCAPI;
+use primtypes::NoArgs;
+use derive::tuple_items;
+
+/// Key and value pairs of a mapping, in the order its `items()` returns them.
+///
+/// Accepts `dict`, `collections.OrderedDict` and any other object with an
+/// `items()` method returning a list of pairs.
+pub fn mapping_entries<'a>(py_object: &PyObject<'a>)
+                           -> Result<Vec<(PyObject<'a>, PyObject<'a>)>, PyError> {
+    let state = py_object.state;
+    let name = CString::new("items").unwrap();
+    if py_object.raw.is_null() ||
+       unsafe { state.PyObject_HasAttrString(py_object.raw, name.as_ptr()) } == 0 {
+        return Err(PyError::FromTypeConversionError);
+    }
+    let items = try!(py_object.call_func("items", NoArgs));
+    let len = unsafe {
+        if state.PyList_Check(items.raw) > 0 {
+            state.PyList_Size(items.raw)
+        } else if state.PyTuple_Check(items.raw) > 0 {
+            state.PyTuple_Size(items.raw)
+        } else {
+            return Err(PyError::FromTypeConversionError);
+        }
+    };
+    try!(tuple_items(&items, len as usize))
+        .iter()
+        .map(|item| {
+            let mut pair = try!(tuple_items(item, 2));
+            let value = pair.remove(1);
+            Ok((pair.remove(0), value))
+        })
+        .collect()
+}
+
+fn mapping_from_py_object<'a, K, V>(state: &'a PyState,
+                                     py_object: PyObject<'a>)
+                                     -> Result<Vec<(K, V)>, PyError>
+    where K: FromPyType<'a>,
+          V: FromPyType<'a>
+{
+    try!(mapping_entries(&py_object))
+        .into_iter()
+        .map(|(key, value)| {
+            let key = try!(state.from_py_object::<K>(key));
+            let value = try!(state.from_py_object::<V>(value));
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Build a `collections.OrderedDict` out of the given entries.
+fn ordered_dict_to_py_object<'a, 'b, K, V, I>(state: &'a PyState,
+                                              entries: I)
+                                              -> Result<PyObject<'a>, PyError>
+    where K: ToPyType + 'b,
+          V: ToPyType + 'b,
+          I: Iterator<Item = (&'b K, &'b V)>
+{
+    let class = try!(state.get_module("collections").and_then(|m| m.get_func("OrderedDict")));
+    let dict = try!(class.call(&PyObject::empty_tuple(state)));
+    for (key, value) in entries {
+        let py_key = try!(key.to_py_object(state));
+        let py_value = try!(value.to_py_object(state));
+        unsafe {
+            if state.PyObject_SetItem(dict.raw, py_key.raw, py_value.raw) != 0 {
+                try!(state.get_result_exception());
+                return Err(PyError::ToTypeConversionError);
+            }
+        }
+    }
+    Ok(dict)
+}
+
+/// Key and value pairs that keep their order through python.
+///
+/// Converts to a `collections.OrderedDict`, as python 2 dictionaries do not
+/// keep insertion order. Extraction accepts any mapping and keeps the order
+/// of its `items()`, which for a plain `dict` is arbitrary but stable.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OrderedMap<K, V>(pub Vec<(K, V)>);
+
+impl<K, V> From<Vec<(K, V)>> for OrderedMap<K, V> {
+    fn from(entries: Vec<(K, V)>) -> OrderedMap<K, V> {
+        OrderedMap(entries)
+    }
+}
+
+impl<K: ToPyType, V: ToPyType> ToPyType for OrderedMap<K, V> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        ordered_dict_to_py_object(state, self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+impl<'a, K: FromPyType<'a>, V: FromPyType<'a>> FromPyType<'a> for OrderedMap<K, V> {
+    fn from_py_object(state: &'a PyState,
+                      py_object: PyObject<'a>)
+                      -> Result<OrderedMap<K, V>, PyError> {
+        mapping_from_py_object(state, py_object).map(OrderedMap)
+    }
+}
+
+/// `IndexMap` converts to a `collections.OrderedDict`, like `OrderedMap`.
+#[cfg(feature = "indexmap")]
+impl<K, V, S> ToPyType for IndexMap<K, V, S>
+    where K: ToPyType + Hash + Eq,
+          V: ToPyType,
+          S: BuildHasher
+{
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        ordered_dict_to_py_object(state, self.iter())
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<'a, K, V, S> FromPyType<'a> for IndexMap<K, V, S>
+    where K: FromPyType<'a> + Hash + Eq,
+          V: FromPyType<'a>,
+          S: BuildHasher + Default
+{
+    fn from_py_object(state: &'a PyState,
+                      py_object: PyObject<'a>)
+                      -> Result<IndexMap<K, V, S>, PyError> {
+        mapping_from_py_object(state, py_object).map(|entries| entries.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, ToPyType};
+    use super::OrderedMap;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    fn entries() -> Vec<(String, isize)> {
+        vec![("zeta".to_string(), 1), ("alpha".to_string(), 2), ("mid".to_string(), 3)]
+    }
+
+    #[test]
+    fn ordered_map_to_ordered_dict_and_back() {
+        let py = PyState::new();
+        let value = OrderedMap(entries());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object),
+                   "PyObject{OrderedDict([('zeta', 1), ('alpha', 2), ('mid', 3)])}");
+        let returned = try_or_panic!(py.from_py_object::<OrderedMap<String, isize>>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn json_config_keeps_key_order() {
+        let py = PyState::new();
+        let source = concat!("import collections, json\n",
+                             "def pairs(items):\n",
+                             "    return collections.OrderedDict((key.encode('utf-8'), value)\n",
+                             "                                   for key, value in items)\n",
+                             "def load(text):\n",
+                             "    return json.loads(text, object_pairs_hook=pairs)\n",
+                             "def dump(config):\n",
+                             "    return json.dumps(config)\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_mapping_json", source));
+        let text = r#"{"zeta": 1, "alpha": 2, "mid": 3}"#.to_string();
+        let config: OrderedMap<String, isize> = try_or_panic!(module.call_func_with_ret("load",
+                                                                                        (text,)));
+        assert_eq!(config.0,
+                   vec![("zeta".to_string(), 1), ("alpha".to_string(), 2), ("mid".to_string(), 3)]);
+        let dumped: String = try_or_panic!(module.call_func_with_ret("dump", (config,)));
+        assert_eq!(dumped, r#"{"zeta": 1, "alpha": 2, "mid": 3}"#);
+    }
+
+    #[test]
+    fn dict_to_ordered_map() {
+        let py = PyState::new();
+        let source = "config = {'a': 1.5}\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_mapping_dict", source));
+        let config = try_or_panic!(module.get_member_obj("config"));
+        let returned = try_or_panic!(py.from_py_object::<OrderedMap<String, f64>>(config));
+        assert_eq!(returned.0, vec![("a".to_string(), 1.5)]);
+    }
+
+    #[test]
+    fn list_to_ordered_map_should_err() {
+        let py = PyState::new();
+        let value = vec![1, 2];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        match py.from_py_object::<OrderedMap<isize, isize>>(py_object) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn index_map_to_py_object_and_back() {
+        use indexmap::IndexMap;
+        let py = PyState::new();
+        let value: IndexMap<String, isize> = entries().into_iter().collect();
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<IndexMap<String, isize>>(py_object));
+        assert_eq!(returned.into_iter().collect::<Vec<_>>(), entries());
+    }
+}
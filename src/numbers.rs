@@ -0,0 +1,212 @@
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::PythonCAPI;
+
+/// Complex number converting to and from python `complex`.
+///
+/// Extraction also accepts python `int` and `float`, which become a complex
+/// number with no imaginary part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex64 {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex64 {
+    pub fn new(re: f64, im: f64) -> Complex64 {
+        Complex64 { re, im }
+    }
+}
+
+impl ToPyType for Complex64 {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        unsafe {
+            let raw = state.PyComplex_FromDoubles(self.re, self.im);
+            if !raw.is_null() && state.PyComplex_Check(raw) > 0 {
+                Ok(PyObject::new(state, raw))
+            } else {
+                Err(PyError::ToTypeConversionError)
+            }
+        }
+    }
+}
+
+impl<'a> FromPyType<'a> for Complex64 {
+    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<Complex64, PyError> {
+        unsafe {
+            let raw = py_object.raw;
+            if !raw.is_null() &&
+               (state.PyComplex_Check(raw) > 0 || state.PyFloat_Check(raw) > 0 ||
+                state.PyInt_Check(raw) > 0) {
+                Ok(Complex64::new(state.PyComplex_RealAsDouble(raw),
+                                  state.PyComplex_ImagAsDouble(raw)))
+            } else {
+                Err(PyError::FromTypeConversionError)
+            }
+        }
+    }
+}
+
+/// Exact decimal number converting to and from python `decimal.Decimal`.
+///
+/// The value is kept as the string python uses to represent it, such as
+/// `"3.14"`, `"-1E+3"` or `"NaN"`, so no precision is lost on the way
+/// through rust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal(pub String);
+
+impl ToPyType for Decimal {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let class = try!(state.get_module("decimal").and_then(|m| m.get_func("Decimal")));
+        let args = (self.0.clone(),);
+        let py_args = try!(args.to_py_object(state));
+        class.call(&py_args)
+    }
+}
+
+impl<'a> FromPyType<'a> for Decimal {
+    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<Decimal, PyError> {
+        let class = try!(state.get_module("decimal").and_then(|m| m.get_member_obj("Decimal")));
+        if try!(py_object.is_instance(&class)) {
+            py_object.str().map(Decimal)
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+/// Exact rational number converting to and from python `fractions.Fraction`.
+///
+/// Numerator and denominator are kept as decimal strings since python
+/// integers are unbounded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fraction {
+    pub numerator: String,
+    pub denominator: String,
+}
+
+impl Fraction {
+    pub fn new(numerator: &str, denominator: &str) -> Fraction {
+        Fraction {
+            numerator: numerator.to_string(),
+            denominator: denominator.to_string(),
+        }
+    }
+}
+
+impl ToPyType for Fraction {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let class = try!(state.get_module("fractions").and_then(|m| m.get_func("Fraction")));
+        let args = (format!("{}/{}", self.numerator, self.denominator),);
+        let py_args = try!(args.to_py_object(state));
+        class.call(&py_args)
+    }
+}
+
+impl<'a> FromPyType<'a> for Fraction {
+    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<Fraction, PyError> {
+        let class = try!(state.get_module("fractions")
+                              .and_then(|m| m.get_member_obj("Fraction")));
+        if try!(py_object.is_instance(&class)) {
+            let numerator = try!(py_object.get_member_obj("numerator").and_then(|x| x.str()));
+            let denominator = try!(py_object.get_member_obj("denominator").and_then(|x| x.str()));
+            Ok(Fraction {
+                numerator,
+                denominator,
+            })
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, ToPyType};
+    use super::{Complex64, Decimal, Fraction};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn complex_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = Complex64::new(1.5, -2.0);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{(1.5-2j)}");
+        let returned = try_or_panic!(py.from_py_object::<Complex64>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn complex_from_python_arithmetic() {
+        let py = PyState::new();
+        let cmath = try_or_panic!(py.get_module("cmath"));
+        let result: Complex64 = try_or_panic!(cmath.call_func_with_ret("sqrt", (-4f64,)));
+        assert_eq!(result, Complex64::new(0.0, 2.0));
+    }
+
+    #[test]
+    fn float_to_complex() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(3f64.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Complex64>(py_object));
+        assert_eq!(returned, Complex64::new(3.0, 0.0));
+    }
+
+    #[test]
+    fn decimal_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = Decimal("0.1000000000000000000000001".to_string());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Decimal>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn decimal_from_python_arithmetic() {
+        let py = PyState::new();
+        let value = Decimal("0.1".to_string());
+        let a = try_or_panic!(value.to_py_object(&py));
+        let sum = try_or_panic!(a.call_func("__add__", (Decimal("0.2".to_string()),)));
+        let returned = try_or_panic!(py.from_py_object::<Decimal>(sum));
+        assert_eq!(returned, Decimal("0.3".to_string()));
+    }
+
+    #[test]
+    fn invalid_decimal_should_err() {
+        let py = PyState::new();
+        let value = Decimal("not a number".to_string());
+        match value.to_py_object(&py) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn float_to_decimal_should_err() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(0.5f64.to_py_object(&py));
+        match py.from_py_object::<Decimal>(py_object) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn fraction_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = Fraction::new("-123456789012345678901234567891", "10");
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Fraction>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn fraction_is_normalized_by_python() {
+        let py = PyState::new();
+        let value = Fraction::new("2", "4");
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Fraction>(py_object));
+        assert_eq!(returned, Fraction::new("1", "2"));
+    }
+}
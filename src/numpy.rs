@@ -0,0 +1,225 @@
+//! Conversions between `NdArray` and `numpy.ndarray`, enabled with the
+//! `numpy` feature.
+//!
+//! numpy is driven through its python API, so it is only needed at run time.
+//! When it cannot be imported, conversions fail with
+//! `PyError::NumpyUnavailable`.
+
+use std::any::type_name;
+use std::mem;
+use std::slice;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use buffer::{Element, ElementKind, PyBuffer};
+use primtypes::{NoArgs, bytes_to_py_object};
+
+/// N-dimensional array stored in C order, converting to and from a
+/// `numpy.ndarray` of the matching dtype.
+///
+/// Extraction accepts arrays with any strides or byte order, copying them to
+/// a contiguous native layout when needed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NdArray<T> {
+    pub shape: Vec<usize>,
+    pub data: Vec<T>,
+}
+
+impl<T> NdArray<T> {
+    /// One dimensional array holding `data`.
+    pub fn from_vec(data: Vec<T>) -> NdArray<T> {
+        NdArray {
+            shape: vec![data.len()],
+            data,
+        }
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+}
+
+fn import_numpy(state: &PyState) -> Result<PyObject<'_>, PyError> {
+    state.get_module("numpy").map_err(|error| {
+        match error {
+            PyError::PyException(message) => PyError::NumpyUnavailable(message),
+            error => error,
+        }
+    })
+}
+
+/// numpy kind character of a dtype holding `T`.
+fn dtype_kind<T: Element>() -> &'static str {
+    match T::kind() {
+        ElementKind::Signed => "i",
+        ElementKind::Unsigned => "u",
+        ElementKind::Float => "f",
+    }
+}
+
+/// Native byte order dtype string of `T`, like `=f8`.
+fn dtype_string<T: Element>() -> String {
+    format!("={}{}", dtype_kind::<T>(), mem::size_of::<T>())
+}
+
+/// Check that a dtype of `kind` and `itemsize`, called `name`, holds `T`.
+fn check_dtype<T: Element>(kind: &str, itemsize: usize, name: &str) -> Result<(), PyError> {
+    if kind != dtype_kind::<T>() || itemsize != mem::size_of::<T>() {
+        return Err(PyError::InvalidBuffer(format!("dtype `{}` cannot be read as {}",
+                                                  name,
+                                                  type_name::<T>())));
+    }
+    Ok(())
+}
+
+impl<T: Element> ToPyType for NdArray<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        if self.shape.iter().product::<usize>() != self.data.len() {
+            return Err(PyError::ToTypeConversionError);
+        }
+        let numpy = try!(import_numpy(state));
+        let bytes = unsafe {
+            slice::from_raw_parts(self.data.as_ptr() as *const u8,
+                                  mem::size_of_val(&self.data[..]))
+        };
+        let data = try!(bytes_to_py_object(state, bytes));
+        let flat = try!(numpy.call_func("frombuffer", (data, dtype_string::<T>())));
+        let shaped = try!(flat.call_func("reshape", (&self.shape,)));
+        // frombuffer shares the immutable string, copy to get a writable array.
+        shaped.call_func("copy", NoArgs)
+    }
+}
+
+impl<'a, T: Element> FromPyType<'a> for NdArray<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<NdArray<T>, PyError> {
+        let numpy = try!(import_numpy(state));
+        let ndarray = try!(numpy.get_member_obj("ndarray"));
+        if !try!(py_object.is_instance(&ndarray)) {
+            return Err(PyError::FromTypeConversionError);
+        }
+        let dtype = try!(py_object.get_member_obj("dtype"));
+        let kind: String = try!(dtype.get_member("kind"));
+        let itemsize: usize = try!(dtype.get_member("itemsize"));
+        let name: String = try!(dtype.get_member("name"));
+        try!(check_dtype::<T>(&kind, itemsize, &name));
+        let shape: Vec<usize> = try!(py_object.get_member("shape"));
+        let native = try!(dtype.call_func("newbyteorder", ("=",)));
+        let contiguous = try!(numpy.call_func("ascontiguousarray", (py_object, native)));
+        let buffer = try!(PyBuffer::get(&contiguous));
+        Ok(NdArray {
+            shape,
+            data: try!(buffer.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, PyError, ToPyType};
+    use super::{NdArray, check_dtype, dtype_string};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    fn has_numpy(py: &PyState) -> bool {
+        py.get_module("numpy").is_ok()
+    }
+
+    #[test]
+    fn missing_numpy_is_a_clear_error() {
+        let py = PyState::new();
+        if has_numpy(&py) {
+            return;
+        }
+        let array = NdArray::from_vec(vec![1.0f64, 2.0]);
+        match array.to_py_object(&py) {
+            Err(PyError::NumpyUnavailable(ref message)) => assert!(message.contains("numpy")),
+            result => panic!("expected numpy to be unavailable but got {:?}", result),
+        };
+        let values = vec![1.0f64, 2.0];
+        let list = try_or_panic!(values.to_py_object(&py));
+        match py.from_py_object::<NdArray<f64>>(list) {
+            Err(PyError::NumpyUnavailable(_)) => (),
+            result => panic!("expected numpy to be unavailable but got {:?}", result),
+        };
+    }
+
+    #[test]
+    #[ignore = "needs numpy installed for python 2.7"]
+    fn ndarray_to_py_object_and_back() {
+        let py = PyState::new();
+        let array = NdArray {
+            shape: vec![2, 3],
+            data: vec![1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0],
+        };
+        let py_object = try_or_panic!(array.to_py_object(&py));
+        let total: f64 = try_or_panic!(py_object.call_func_with_ret("sum", ::NoArgs));
+        assert_eq!(total, 21.0);
+        let returned = try_or_panic!(py.from_py_object::<NdArray<f64>>(py_object));
+        assert_eq!(returned, array);
+    }
+
+    #[test]
+    #[ignore = "needs numpy installed for python 2.7"]
+    fn strided_array_is_read_in_c_order() {
+        let py = PyState::new();
+        let source = concat!("import numpy\n",
+                             "transposed = numpy.arange(6, dtype='int32').reshape(2, 3).T\n",
+                             "swapped = numpy.array([1.5, 2.5], dtype='>f8')\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_numpy", source));
+        let transposed: NdArray<i32> = try_or_panic!(module.get_member("transposed"));
+        assert_eq!(transposed.shape, vec![3, 2]);
+        assert_eq!(transposed.data, vec![0, 3, 1, 4, 2, 5]);
+        let swapped: NdArray<f64> = try_or_panic!(module.get_member("swapped"));
+        assert_eq!(swapped.data, vec![1.5, 2.5]);
+    }
+
+    #[test]
+    #[ignore = "needs numpy installed for python 2.7"]
+    fn wrong_dtype_should_err() {
+        let py = PyState::new();
+        let array = NdArray::from_vec(vec![1i32, 2, 3]);
+        let py_object = try_or_panic!(array.to_py_object(&py));
+        match py.from_py_object::<NdArray<f32>>(py_object.clone()) {
+            Err(PyError::InvalidBuffer(ref message)) => assert!(message.contains("int32")),
+            result => panic!("expected a dtype error but got {:?}", result),
+        }
+        assert!(py.from_py_object::<NdArray<i64>>(py_object).is_err());
+    }
+
+    #[test]
+    fn native_dtype_strings() {
+        assert_eq!(dtype_string::<i8>(), "=i1");
+        assert_eq!(dtype_string::<i64>(), "=i8");
+        assert_eq!(dtype_string::<u16>(), "=u2");
+        assert_eq!(dtype_string::<u32>(), "=u4");
+        assert_eq!(dtype_string::<f32>(), "=f4");
+        assert_eq!(dtype_string::<f64>(), "=f8");
+    }
+
+    #[test]
+    fn dtype_validation() {
+        try_or_panic!(check_dtype::<f64>("f", 8, "float64"));
+        try_or_panic!(check_dtype::<u8>("u", 1, "uint8"));
+        match check_dtype::<f32>("i", 4, "int32") {
+            Err(PyError::InvalidBuffer(ref message)) => {
+                assert!(message.contains("int32") && message.contains("f32"))
+            }
+            result => panic!("expected a dtype error but got {:?}", result),
+        };
+        assert!(check_dtype::<i64>("i", 4, "int32").is_err());
+        assert!(check_dtype::<u32>("i", 4, "int32").is_err());
+        assert!(check_dtype::<f64>("c", 8, "complex64").is_err());
+    }
+
+    #[test]
+    fn shape_not_matching_data_should_err() {
+        let py = PyState::new();
+        let array = NdArray {
+            shape: vec![2, 2],
+            data: vec![1u8, 2, 3],
+        };
+        match array.to_py_object(&py) {
+            Err(PyError::ToTypeConversionError) => (),
+            result => panic!("expected a conversion error but got {:?}", result),
+        };
+    }
+}
@@ -0,0 +1,408 @@
+//! Redirection of `sys.stdout` and `sys.stderr` to rust writers.
+//!
+//! Python writes to a `RustWriter` file object, whose `write` is a C
+//! function bound to a capsule holding the rust writer. The capsule is
+//! emptied once the redirection ends, so a writer kept by python code can
+//! not reach a rust writer that is gone.
+
+use std::ffi::CString;
+use std::io::{self, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use libc::{c_char, c_void};
+use base::{PyObject, PyState, PyError};
+use ffi::{PythonCAPI, PyObjectRaw, PyMethodDef, METH_O, Unbound};
+use primtypes::NoArgs;
+
+const HELPER: &str = "_rustpy_output";
+
+const HELPER_SOURCE: &str = r#"
+class RustWriter(object):
+    """File object writing to a rust `Write`."""
+
+    softspace = 0
+    encoding = 'utf-8'
+    errors = 'strict'
+    closed = False
+
+    def __init__(self, write):
+        self._write = write
+
+    def write(self, data):
+        if isinstance(data, unicode):
+            data = data.encode('utf-8')
+        elif not isinstance(data, str):
+            raise TypeError('expected a character buffer object')
+        error = self._write(data)
+        if error is not None:
+            raise IOError(error)
+
+    def writelines(self, lines):
+        for line in lines:
+            self.write(line)
+
+    def flush(self):
+        error = self._write(None)
+        if error is not None:
+            raise IOError(error)
+
+    def isatty(self):
+        return False
+"#;
+
+const CAPSULE_NAME: &[u8] = b"rustpy.writer\0";
+
+/// Text python wrote to `sys.stdout` and `sys.stderr`, decoded as UTF-8.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CapturedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Rust writer reached from python, `None` once the redirection ended.
+struct Slot {
+    writer: Option<*mut (dyn Write + Send + 'static)>,
+}
+
+static WRITE_METHOD: PyMethodDef = PyMethodDef {
+    ml_name: b"write\0" as *const u8 as *const c_char,
+    ml_meth: write_callback,
+    ml_flags: METH_O,
+    ml_doc: ptr::null(),
+};
+
+/// Raise a `TypeError` with `message`, a nul terminated string.
+unsafe fn type_error(capi: &Unbound, message: &[u8]) -> *mut PyObjectRaw {
+    capi.PyErr_SetString(capi.PyExc_TypeError(), message.as_ptr() as *const c_char);
+    ptr::null_mut()
+}
+
+/// Write the `str` `data` to the writer in `capsule`, or flush it when
+/// `data` is `None`. Returns `None`, or the error message as a `str`.
+unsafe extern "C" fn write_callback(capsule: *mut PyObjectRaw,
+                                    data: *mut PyObjectRaw)
+                                    -> *mut PyObjectRaw {
+    let capi = Unbound;
+    let slot = capi.PyCapsule_GetPointer(capsule, CAPSULE_NAME.as_ptr() as *const c_char) as
+               *mut Slot;
+    if slot.is_null() {
+        return ptr::null_mut();
+    }
+    let flush = data == capi.Py_None();
+    if !flush && capi.PyString_Check(data) == 0 {
+        return type_error(&capi, b"expected a str or None\0");
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let writer = match (*slot).writer {
+            Some(writer) => &mut *writer,
+            None => return Err(io::Error::other("rust writer is closed")),
+        };
+        if flush {
+            writer.flush()
+        } else {
+            let bytes = slice::from_raw_parts(capi.PyString_AsString(data) as *const u8,
+                                              capi.PyString_Size(data) as usize);
+            writer.write_all(bytes)
+        }
+    }));
+    let message = match result {
+        Ok(Ok(())) => {
+            let none = capi.Py_None();
+            capi.Py_IncRef(none);
+            return none;
+        }
+        Ok(Err(error)) => error.to_string(),
+        Err(_) => "rust writer panicked".to_string(),
+    };
+    let message = CString::new(message).unwrap_or_default();
+    capi.PyString_FromString(message.as_ptr())
+}
+
+unsafe extern "C" fn drop_slot(capsule: *mut PyObjectRaw) {
+    let pointer = Unbound.PyCapsule_GetPointer(capsule, CAPSULE_NAME.as_ptr() as *const c_char);
+    drop(Box::from_raw(pointer as *mut Slot));
+}
+
+/// Python file object writing to `writer`, and the capsule holding the slot
+/// to empty when the writer goes away.
+fn rust_writer<'a>(state: &'a PyState,
+                   writer: &mut (dyn Write + Send))
+                   -> Result<(PyObject<'a>, PyObject<'a>), PyError> {
+    let helper = try!(state.module_from_source(HELPER, HELPER_SOURCE));
+    unsafe {
+        // The slot is emptied before the writer goes out of scope.
+        let writer: *mut (dyn Write + Send + 'static) = mem::transmute(writer);
+        let slot = Box::into_raw(Box::new(Slot { writer: Some(writer) }));
+        let capsule = state.PyCapsule_New(slot as *mut c_void,
+                                          CAPSULE_NAME.as_ptr() as *const c_char,
+                                          Some(drop_slot));
+        if capsule.is_null() {
+            drop(Box::from_raw(slot));
+            try!(state.get_result_exception());
+            return Err(PyError::NullPyObject);
+        }
+        let capsule = PyObject::new(state, capsule);
+        let method = &WRITE_METHOD as *const PyMethodDef as *mut PyMethodDef;
+        let write = state.PyCFunction_NewEx(method, capsule.raw, ptr::null_mut());
+        try!(state.get_result_exception());
+        let write = PyObject::new(state, write);
+        let py_writer = try!(helper.call_func("RustWriter", (write,)));
+        Ok((py_writer, capsule))
+    }
+}
+
+/// Puts back the previous `sys` stream and empties the slot of the writer.
+///
+/// Holds a reference to the capsule, so the slot outlives the redirection
+/// even when python rebinds the `sys` stream and drops the writer.
+struct Redirect<'a> {
+    state: &'a PyState,
+    name: &'static [u8],
+    previous: Option<PyObject<'a>>,
+    capsule: PyObject<'a>,
+}
+
+impl<'a> Redirect<'a> {
+    fn new(state: &'a PyState,
+           name: &'static [u8],
+           writer: &mut (dyn Write + Send))
+           -> Result<Redirect<'a>, PyError> {
+        let (py_writer, capsule) = try!(rust_writer(state, writer));
+        unsafe {
+            let previous = state.PySys_GetObject(name.as_ptr() as *const c_char);
+            let previous = if previous.is_null() {
+                None
+            } else {
+                state.Py_IncRef(previous);
+                Some(PyObject::new(state, previous))
+            };
+            let redirect = Redirect {
+                state,
+                name,
+                previous,
+                capsule,
+            };
+            if state.PySys_SetObject(name.as_ptr() as *const c_char, py_writer.raw) != 0 {
+                try!(state.get_result_exception());
+            }
+            Ok(redirect)
+        }
+    }
+}
+
+impl<'a> Drop for Redirect<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            let slot = self.state.PyCapsule_GetPointer(self.capsule.raw,
+                                                       CAPSULE_NAME.as_ptr() as *const c_char);
+            (*(slot as *mut Slot)).writer = None;
+            let previous = match self.previous {
+                Some(ref previous) => previous.raw,
+                None => ptr::null_mut(),
+            };
+            self.state.PySys_SetObject(self.name.as_ptr() as *const c_char, previous);
+            let _ = self.state.get_result_exception();
+        }
+    }
+}
+
+pub fn redirect<T, F>(state: &PyState,
+                      stdout: &mut (dyn Write + Send),
+                      stderr: &mut (dyn Write + Send),
+                      f: F)
+                      -> Result<T, PyError>
+    where F: FnOnce() -> T
+{
+    let _stdout = try!(Redirect::new(state, b"stdout\0", stdout));
+    let _stderr = try!(Redirect::new(state, b"stderr\0", stderr));
+    let result = f();
+    let sys = try!(state.get_module("sys"));
+    for name in &["stdout", "stderr"] {
+        try!(try!(sys.get_member_obj(name)).call_func("flush", NoArgs));
+    }
+    Ok(result)
+}
+
+pub fn capture<T, F>(state: &PyState, f: F) -> Result<(T, CapturedOutput), PyError>
+    where F: FnOnce() -> T
+{
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let result = try!(redirect(state, &mut stdout, &mut stderr, f));
+    let output = CapturedOutput {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+    };
+    Ok((result, output))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Mutex, MutexGuard};
+    use base::{PyState, PyError};
+    use primtypes::NoArgs;
+    use super::CapturedOutput;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    // `sys.stdout` and `sys.stderr` are shared by the test threads, redirect
+    // from one test at a time.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn serial() -> MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    const SOURCE: &str = concat!("# -*- coding: utf-8 -*-\n",
+                                 "import sys\n",
+                                 "def talk():\n",
+                                 "    print 'hello', 42\n",
+                                 "    print u'caf\\xe9'\n",
+                                 "    sys.stderr.write('warning\\n')\n",
+                                 "def keep():\n",
+                                 "    global kept\n",
+                                 "    kept = sys.stdout\n",
+                                 "def write_kept():\n",
+                                 "    kept.write('late')\n");
+
+    #[test]
+    fn capture_print_and_stderr() {
+        let _serial = serial();
+        let py = PyState::new();
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_output", SOURCE));
+        let (result, output) = try_or_panic!(py.capture_output(|| {
+            module.call_func("talk", NoArgs)
+        }));
+        try_or_panic!(result);
+        assert_eq!(output,
+                   CapturedOutput {
+                       stdout: "hello 42\ncafé\n".to_string(),
+                       stderr: "warning\n".to_string(),
+                   });
+    }
+
+    #[test]
+    fn streams_are_restored() {
+        let _serial = serial();
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let before = try_or_panic!(sys.get_member_obj("stdout"));
+        let (_, output) = try_or_panic!(py.capture_output(|| ()));
+        assert_eq!(output, CapturedOutput::default());
+        let after = try_or_panic!(sys.get_member_obj("stdout"));
+        assert_eq!(before.raw, after.raw);
+    }
+
+    #[test]
+    fn streams_are_restored_after_panic() {
+        let _serial = serial();
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let before = try_or_panic!(sys.get_member_obj("stderr"));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            py.capture_output(|| panic!("closure panicked"))
+        }));
+        assert!(result.is_err());
+        let after = try_or_panic!(sys.get_member_obj("stderr"));
+        assert_eq!(before.raw, after.raw);
+    }
+
+    #[test]
+    fn nested_redirections() {
+        let _serial = serial();
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let ((_, inner), outer) = try_or_panic!(py.capture_output(|| {
+            try_or_panic!(try_or_panic!(sys.get_member_obj("stdout")).call_func("write", ("a",)));
+            let inner = try_or_panic!(py.capture_output(|| {
+                let stdout = try_or_panic!(sys.get_member_obj("stdout"));
+                try_or_panic!(stdout.call_func("write", ("b",)));
+            }));
+            try_or_panic!(try_or_panic!(sys.get_member_obj("stdout")).call_func("write", ("c",)));
+            inner
+        }));
+        assert_eq!(inner.stdout, "b");
+        assert_eq!(outer.stdout, "ac");
+    }
+
+    #[test]
+    fn rebinding_streams_during_capture() {
+        let _serial = serial();
+        let py = PyState::new();
+        let source = concat!("import gc, sys\n",
+                             "def rebind():\n",
+                             "    sys.stdout.write('before')\n",
+                             "    sys.stdout = sys.__stdout__\n",
+                             "    sys.stderr = sys.__stderr__\n",
+                             "    gc.collect()\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_output_rebind", source));
+        let (result, output) = try_or_panic!(py.capture_output(|| {
+            module.call_func("rebind", NoArgs)
+        }));
+        try_or_panic!(result);
+        assert_eq!(output.stdout, "before");
+    }
+
+    #[test]
+    fn kept_writer_is_closed() {
+        let _serial = serial();
+        let py = PyState::new();
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_output_kept", SOURCE));
+        let (result, _) = try_or_panic!(py.capture_output(|| module.call_func("keep", NoArgs)));
+        try_or_panic!(result);
+        match module.call_func("write_kept", NoArgs) {
+            Err(PyError::PyException(ref message)) => assert!(message.contains("closed")),
+            result => panic!("expected a closed writer but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn writing_other_types_raises_type_error() {
+        let _serial = serial();
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let (result, output) = try_or_panic!(py.capture_output(|| {
+            let stdout = try_or_panic!(sys.get_member_obj("stdout"));
+            stdout.call_func("_write", (1isize,))
+        }));
+        match result {
+            Err(PyError::PyException(ref message)) => assert!(message.contains("TypeError")),
+            result => panic!("expected a TypeError but got {:?}", result),
+        };
+        assert_eq!(output, CapturedOutput::default());
+    }
+
+    struct Failing;
+
+    impl Write for Failing {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writer_errors_raise_io_error() {
+        let _serial = serial();
+        let py = PyState::new();
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_output_failing", SOURCE));
+        let mut stderr = Vec::new();
+        let result = try_or_panic!(py.redirect_output(&mut Failing,
+                                                      &mut stderr,
+                                                      || module.call_func("talk", NoArgs)));
+        match result {
+            Err(PyError::PyException(ref message)) => {
+                assert!(message.contains("IOError") && message.contains("disk full"))
+            }
+            result => panic!("expected an IOError but got {:?}", result),
+        };
+    }
+}
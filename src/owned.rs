@@ -0,0 +1,259 @@
+//! Python objects owned without borrowing a `PyState`.
+//!
+//! An `OwnedPyObject` holds a strong reference that can be stored in long
+//! lived structs or sent to another thread. Its reference is dropped at once
+//! when the thread holds the GIL, and otherwise queued until the next
+//! `PyState` is created on any thread.
+
+use std::fmt;
+use std::mem;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::{PythonCAPI, PyObjectRaw, Unbound};
+
+/// Number of times the interpreter was finalized. Objects owned in an
+/// earlier interpreter are gone with it.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// References dropped without the GIL, with their generation.
+static PENDING: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
+/// Whether the current thread holds the GIL of the main interpreter.
+unsafe fn holds_gil() -> bool {
+    let capi = Unbound;
+    if capi.Py_IsInitialized() == 0 {
+        return false;
+    }
+    let current = capi.PyThreadState_GET();
+    !current.is_null() && current == capi.PyGILState_GetThisThreadState()
+}
+
+/// Drop the references queued by other threads, called with the GIL held.
+pub fn release_pending(state: &PyState) {
+    let pending = {
+        let mut pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        mem::take(&mut *pending)
+    };
+    let generation = GENERATION.load(Ordering::SeqCst);
+    for (raw, owned_generation) in pending {
+        if owned_generation == generation {
+            unsafe {
+                state.Py_DecRef(raw as *mut PyObjectRaw);
+            }
+        }
+    }
+}
+
+/// Forget every owned object, called once the interpreter is finalized.
+pub fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+}
+
+/// Strong reference to a python object of the main interpreter, which is
+/// `Send` and `Sync` and bound to a `PyState` to be used.
+///
+/// Binding fails once the interpreter the object belongs to was finalized.
+pub struct OwnedPyObject {
+    raw: *mut PyObjectRaw,
+    generation: usize,
+}
+
+unsafe impl Send for OwnedPyObject {}
+unsafe impl Sync for OwnedPyObject {}
+
+impl OwnedPyObject {
+    /// Own a new reference to `py_object`.
+    ///
+    /// Objects of a sub-interpreter can not be owned, they must not leave it.
+    pub fn new(py_object: &PyObject) -> Result<OwnedPyObject, PyError> {
+        let state = py_object.state;
+        unsafe {
+            if state.PyThreadState_GET() != state.PyGILState_GetThisThreadState() {
+                let message = "object of a sub-interpreter".to_string();
+                return Err(PyError::InvalidOwnedObject(message));
+            }
+            state.Py_IncRef(py_object.raw);
+        }
+        Ok(OwnedPyObject {
+            raw: py_object.raw,
+            generation: GENERATION.load(Ordering::SeqCst),
+        })
+    }
+
+    /// Borrow the object for the lifetime of `state`.
+    pub fn bind<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        unsafe {
+            // Queued objects must not be released inside a sub-interpreter either.
+            if state.PyThreadState_GET() != state.PyGILState_GetThisThreadState() {
+                let message = "bound in a sub-interpreter".to_string();
+                return Err(PyError::InvalidOwnedObject(message));
+            }
+        }
+        release_pending(state);
+        if self.generation != GENERATION.load(Ordering::SeqCst) {
+            return Err(PyError::InvalidOwnedObject("interpreter was finalized".to_string()));
+        }
+        unsafe {
+            state.Py_IncRef(self.raw);
+        }
+        Ok(PyObject::new(state, self.raw))
+    }
+
+    /// Own another reference to the same object.
+    pub fn clone_ref(&self, state: &PyState) -> Result<OwnedPyObject, PyError> {
+        let py_object = try!(self.bind(state));
+        OwnedPyObject::new(&py_object)
+    }
+}
+
+impl Drop for OwnedPyObject {
+    fn drop(&mut self) {
+        unsafe {
+            if !holds_gil() {
+                let mut pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                pending.push((self.raw as usize, self.generation));
+            } else if self.generation == GENERATION.load(Ordering::SeqCst) {
+                Unbound.Py_DecRef(self.raw);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for OwnedPyObject {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "OwnedPyObject{{{:p}}}", self.raw)
+    }
+}
+
+impl ToPyType for OwnedPyObject {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        self.bind(state)
+    }
+}
+
+impl<'a> FromPyType<'a> for OwnedPyObject {
+    fn from_py_object(_: &'a PyState, py_object: PyObject<'a>) -> Result<OwnedPyObject, PyError> {
+        OwnedPyObject::new(&py_object)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::thread;
+    use base::{PyState, PyObject, PyError, ToPyType};
+    use primtypes::NoArgs;
+    use super::OwnedPyObject;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    /// References to `py_object`, without running python code that could let
+    /// another thread take the GIL and drop the queued references meanwhile.
+    fn refcount(sys: &PyObject, py_object: &PyObject) -> isize {
+        try_or_panic!(sys.call_func_with_ret("getrefcount", (py_object,)))
+    }
+
+    #[test]
+    fn owned_object_is_bound_on_another_thread() {
+        let owned = {
+            let py = PyState::new();
+            let value = vec![1isize, 2, 3];
+            let py_object = try_or_panic!(value.to_py_object(&py));
+            try_or_panic!(OwnedPyObject::new(&py_object))
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+                let py = PyState::new();
+                let py_object = try_or_panic!(owned.bind(&py));
+                let total: isize = try_or_panic!(py_object.call_func_with_ret("__len__", NoArgs));
+                sender.send((total, owned)).unwrap();
+            })
+            .join()
+            .unwrap();
+        let (total, owned) = receiver.recv().unwrap();
+        assert_eq!(total, 3);
+        let py = PyState::new();
+        let py_object = try_or_panic!(owned.bind(&py));
+        let value: Vec<isize> = try_or_panic!(py.from_py_object(py_object));
+        assert_eq!(value, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn drop_without_gil_is_deferred() {
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let value = vec![1isize];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let owned = try_or_panic!(OwnedPyObject::new(&py_object));
+        let before = refcount(&sys, &py_object);
+        thread::spawn(move || drop(owned)).join().unwrap();
+        assert_eq!(refcount(&sys, &py_object), before);
+        // Creating a state drops the references queued by other threads.
+        drop(PyState::new());
+        assert_eq!(refcount(&sys, &py_object), before - 1);
+    }
+
+    #[test]
+    fn drop_with_gil_is_immediate() {
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        let value = vec![1isize];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let owned = try_or_panic!(OwnedPyObject::new(&py_object));
+        let other = try_or_panic!(owned.clone_ref(&py));
+        let before = refcount(&sys, &py_object);
+        drop(other);
+        assert_eq!(refcount(&sys, &py_object), before - 1);
+    }
+
+    #[test]
+    fn owned_objects_convert_inside_containers() {
+        let py = PyState::new();
+        let value = vec![vec![1isize], vec![2, 3]];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let owned: Vec<OwnedPyObject> = try_or_panic!(py.from_py_object(py_object));
+        let list = try_or_panic!(owned.to_py_object(&py));
+        let returned: Vec<Vec<isize>> = try_or_panic!(py.from_py_object(list));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn binding_in_sub_interpreter_keeps_pending_references() {
+        let py = PyState::new();
+        let sys = try_or_panic!(py.get_module("sys"));
+        // Created first, starting it runs python code.
+        let sub = try_or_panic!(::SubInterpreter::new(&py));
+        let value = vec![1isize];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let owned = try_or_panic!(OwnedPyObject::new(&py_object));
+        let queued = try_or_panic!(owned.clone_ref(&py));
+        let before = refcount(&sys, &py_object);
+        thread::spawn(move || drop(queued)).join().unwrap();
+        let result = sub.enter(move |py| owned.bind(py).err());
+        match result {
+            Some(PyError::InvalidOwnedObject(_)) => (),
+            result => panic!("expected an invalid owned object but got {:?}", result),
+        };
+        // `owned` was dropped inside the sub-interpreter, so it is queued too.
+        assert_eq!(refcount(&sys, &py_object), before);
+        drop(PyState::new());
+        assert_eq!(refcount(&sys, &py_object), before - 2);
+    }
+
+    #[test]
+    fn sub_interpreter_objects_can_not_be_owned() {
+        let py = PyState::new();
+        let sub = try_or_panic!(::SubInterpreter::new(&py));
+        let result = sub.enter(|py| {
+            let sys = try_or_panic!(py.get_module("sys"));
+            OwnedPyObject::new(&sys).err()
+        });
+        match result {
+            Some(PyError::InvalidOwnedObject(_)) => (),
+            result => panic!("expected an invalid owned object but got {:?}", result),
+        };
+    }
+}
@@ -0,0 +1,219 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::PythonCAPI;
+use primtypes::{NoArgs, bytes_to_py_object, bytes_from_py_object};
+
+#[cfg(unix)]
+fn os_str_to_py_object<'a>(state: &'a PyState, value: &OsStr) -> Result<PyObject<'a>, PyError> {
+    bytes_to_py_object(state, value.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn os_str_to_py_object<'a>(state: &'a PyState, value: &OsStr) -> Result<PyObject<'a>, PyError> {
+    match value.to_str() {
+        Some(string) => bytes_to_py_object(state, string.as_bytes()),
+        None => Err(PyError::StringConversionError),
+    }
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Result<OsString, PyError> {
+    Ok(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Result<OsString, PyError> {
+    String::from_utf8(bytes).map(OsString::from).map_err(|_| PyError::StringConversionError)
+}
+
+/// Read a `str` as raw bytes, or a `unicode` encoded with the filesystem
+/// encoding.
+fn os_string_from_string(state: &PyState, py_object: &PyObject) -> Result<OsString, PyError> {
+    unsafe {
+        if state.PyString_Check(py_object.raw) > 0 {
+            bytes_from_py_object(state, py_object).and_then(os_string_from_bytes)
+        } else if state.PyUnicode_Check(py_object.raw) > 0 {
+            let sys = try!(state.get_module("sys"));
+            let encoding = try!(sys.call_func("getfilesystemencoding", NoArgs));
+            let encoding = if encoding.is_none() {
+                "utf-8".to_string()
+            } else {
+                try!(state.from_py_object::<String>(encoding))
+            };
+            let encoded = try!(py_object.call_func("encode", (encoding,)));
+            bytes_from_py_object(state, &encoded).and_then(os_string_from_bytes)
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+/// Check for a `pathlib.PurePath`, which predates `__fspath__` on python 2.
+fn is_pure_path(py_object: &PyObject) -> bool {
+    ["pathlib", "pathlib2"].iter().any(|name| {
+        py_object.state
+                 .get_module(name)
+                 .and_then(|module| module.get_member_obj("PurePath"))
+                 .and_then(|class| py_object.is_instance(&class))
+                 .unwrap_or(false)
+    })
+}
+
+/// Extract a path from a `str`, a `unicode` or a path-like object.
+///
+/// Path-like objects are read through `__fspath__` when they have one, and
+/// through `str()` for `pathlib` paths.
+fn os_string_from_py_object(state: &PyState, py_object: PyObject) -> Result<OsString, PyError> {
+    if py_object.raw.is_null() {
+        return Err(PyError::FromTypeConversionError);
+    }
+    match os_string_from_string(state, &py_object) {
+        Err(PyError::FromTypeConversionError) => (),
+        result => return result,
+    }
+    if let Ok(fspath) = py_object.get_func("__fspath__") {
+        let path = try!(fspath.call(&PyObject::empty_tuple(state)));
+        os_string_from_string(state, &path)
+    } else if is_pure_path(&py_object) {
+        let path = unsafe {
+            let raw = state.PyObject_Str(py_object.raw);
+            if raw.is_null() {
+                try!(state.get_result_exception());
+                return Err(PyError::NullPyObject);
+            }
+            PyObject::new(state, raw)
+        };
+        os_string_from_string(state, &path)
+    } else {
+        Err(PyError::FromTypeConversionError)
+    }
+}
+
+/// Paths convert to a python `str` holding the raw bytes of the path, so
+/// names that are not valid UTF-8 survive the round trip on unix.
+impl ToPyType for Path {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        os_str_to_py_object(state, self.as_os_str())
+    }
+}
+
+impl ToPyType for PathBuf {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        os_str_to_py_object(state, self.as_os_str())
+    }
+}
+
+impl<'a> FromPyType<'a> for PathBuf {
+    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<PathBuf, PyError> {
+        os_string_from_py_object(state, py_object).map(PathBuf::from)
+    }
+}
+
+impl ToPyType for OsStr {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        os_str_to_py_object(state, self)
+    }
+}
+
+impl ToPyType for OsString {
+    fn to_py_object<'a>(&self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        os_str_to_py_object(state, self)
+    }
+}
+
+impl<'a> FromPyType<'a> for OsString {
+    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<OsString, PyError> {
+        os_string_from_py_object(state, py_object)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    use base::{PyState, ToPyType};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn path_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = PathBuf::from("/tmp/some dir/file.txt");
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<PathBuf>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path_keeps_its_bytes() {
+        use std::os::unix::ffi::OsStringExt;
+        let py = PyState::new();
+        let value = PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9".to_vec()));
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let size: isize = try_or_panic!(py_object.call_func_with_ret("__len__", ::NoArgs));
+        assert_eq!(size, 9);
+        let returned = try_or_panic!(py.from_py_object::<PathBuf>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn os_path_join() {
+        let py = PyState::new();
+        let os_path = try_or_panic!(py.get_module("os.path"));
+        let args = (PathBuf::from("/tmp"), OsString::from("file.txt"));
+        let joined: PathBuf = try_or_panic!(os_path.call_func_with_ret("join", args));
+        assert_eq!(joined, PathBuf::from("/tmp/file.txt"));
+    }
+
+    #[test]
+    fn unicode_to_path() {
+        let py = PyState::new();
+        let value = "/tmp/unicode".to_string();
+        let encoded = try_or_panic!(value.to_py_object(&py));
+        let unicode = try_or_panic!(encoded.call_func("decode", ("ascii".to_string(),)));
+        let returned = try_or_panic!(py.from_py_object::<PathBuf>(unicode));
+        assert_eq!(returned, PathBuf::from("/tmp/unicode"));
+    }
+
+    #[test]
+    fn fspath_object_to_path() {
+        let py = PyState::new();
+        let source = concat!("class Path(object):\n",
+                             "    def __fspath__(self):\n",
+                             "        return '/tmp/fspath'\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_path", source));
+        let path = try_or_panic!(module.call_func("Path", ::NoArgs));
+        let returned = try_or_panic!(py.from_py_object::<PathBuf>(path));
+        assert_eq!(returned, PathBuf::from("/tmp/fspath"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_pure_path_keeps_its_bytes() {
+        use std::os::unix::ffi::OsStringExt;
+        let py = PyState::new();
+        // Stands in for `pathlib2`, which is not always installed.
+        let source = concat!("class PurePath(object):\n",
+                             "    def __str__(self):\n",
+                             "        return '/tmp/caf\\xe9'\n");
+        let pathlib = try_or_panic!(py.module_from_source("pathlib2", source));
+        let path = try_or_panic!(pathlib.call_func("PurePath", ::NoArgs));
+        let returned = try_or_panic!(py.from_py_object::<PathBuf>(path));
+        assert_eq!(returned, PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9".to_vec())));
+    }
+
+    #[test]
+    fn int_to_path_should_err() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(3isize.to_py_object(&py));
+        match py.from_py_object::<PathBuf>(py_object) {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+}
@@ -0,0 +1,148 @@
+//! Serialization of python objects with `cPickle`, or `pickle` when the C
+//! module is missing.
+
+use base::{PyObject, PyState, PyError};
+use primtypes::{bytes_to_py_object, bytes_from_py_object};
+
+const HELPER: &str = "_rustpy_pickle";
+
+const HELPER_SOURCE: &str = r#"
+import sys
+try:
+    import cPickle as pickle
+except ImportError:
+    import pickle
+try:
+    from cStringIO import StringIO
+except ImportError:
+    from StringIO import StringIO
+
+
+def dumps(obj, protocol):
+    return pickle.dumps(obj, protocol)
+
+
+def loads(data):
+    return pickle.loads(data)
+
+
+def loads_restricted(data, allowed):
+    allowed = frozenset(allowed)
+
+    def find_global(module, name):
+        if module + '.' + name not in allowed:
+            raise pickle.UnpicklingError('global %s.%s is not allowed' % (module, name))
+        __import__(module)
+        return getattr(sys.modules[module], name)
+
+    unpickler = pickle.Unpickler(StringIO(data))
+    if hasattr(unpickler, 'find_class'):
+        unpickler.find_class = find_global
+    else:
+        unpickler.find_global = find_global
+    return unpickler.load()
+"#;
+
+fn helper(state: &PyState) -> Result<PyObject<'_>, PyError> {
+    state.module_from_source(HELPER, HELPER_SOURCE)
+}
+
+/// Report python exceptions raised while pickling as `PyError::Pickle`.
+fn pickle_error(error: PyError) -> PyError {
+    match error {
+        PyError::PyException(message) => PyError::Pickle(message),
+        error => error,
+    }
+}
+
+pub fn dumps(py_object: &PyObject, protocol: isize) -> Result<Vec<u8>, PyError> {
+    let state = py_object.state;
+    let helper = try!(helper(state));
+    let data = try!(helper.call_func("dumps", (py_object.clone(), protocol)).map_err(pickle_error));
+    bytes_from_py_object(state, &data)
+}
+
+pub fn loads<'a>(state: &'a PyState, data: &[u8]) -> Result<PyObject<'a>, PyError> {
+    let data = try!(bytes_to_py_object(state, data));
+    try!(helper(state)).call_func("loads", (data,)).map_err(pickle_error)
+}
+
+pub fn loads_restricted<'a>(state: &'a PyState,
+                            data: &[u8],
+                            allowed: &[&str])
+                            -> Result<PyObject<'a>, PyError> {
+    let data = try!(bytes_to_py_object(state, data));
+    try!(helper(state)).call_func("loads_restricted", (data, allowed)).map_err(pickle_error)
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, PyError, ToPyType};
+    use mapping::OrderedMap;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn pickle_and_back() {
+        let py = PyState::new();
+        let value = vec![(1, "one".to_string()), (2, "two".to_string())];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let data = try_or_panic!(py_object.to_pickle(-1));
+        let returned = try_or_panic!(py.from_pickle(&data));
+        assert_eq!(try_or_panic!(py.from_py_object::<Vec<(isize, String)>>(returned)), value);
+    }
+
+    #[test]
+    fn pickle_protocol_zero_is_text() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(3isize.to_py_object(&py));
+        let data = try_or_panic!(py_object.to_pickle(0));
+        assert_eq!(data, b"I3\n.");
+    }
+
+    #[test]
+    fn unpicklable_object_should_err() {
+        let py = PyState::new();
+        let module = try_or_panic!(py.get_module("os"));
+        match module.to_pickle(2) {
+            Err(PyError::Pickle(ref message)) => assert!(message.contains("module")),
+            result => panic!("expected a pickle error but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn invalid_pickle_should_err() {
+        let py = PyState::new();
+        match py.from_pickle(b"not a pickle") {
+            Err(PyError::Pickle(_)) => (),
+            result => panic!("expected a pickle error but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn restricted_load_allows_listed_classes() {
+        let py = PyState::new();
+        let value = OrderedMap(vec![("b".to_string(), 1), ("a".to_string(), 2)]);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let data = try_or_panic!(py_object.to_pickle(2));
+        let returned = try_or_panic!(py.from_pickle_restricted(&data,
+                                                               &["collections.OrderedDict"]));
+        assert_eq!(try_or_panic!(py.from_py_object::<OrderedMap<String, isize>>(returned)),
+                   value);
+        match py.from_pickle_restricted(&data, &[]) {
+            Err(PyError::Pickle(ref message)) => assert!(message.contains("OrderedDict")),
+            result => panic!("expected a pickle error but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn restricted_load_rejects_other_globals() {
+        let py = PyState::new();
+        let data = b"cos\nsystem\n(S'echo unpickled'\ntR.";
+        match py.from_pickle_restricted(data, &["collections.OrderedDict"]) {
+            Err(PyError::Pickle(ref message)) => assert!(message.contains("os.system")),
+            result => panic!("expected a pickle error but got {:?}", result),
+        };
+    }
+}
@@ -1,5 +1,11 @@
-use libc::{c_long, size_t};
+use libc::{c_char, c_long, size_t};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::ffi::{CStr, CString};
+use std::iter::FromIterator;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::slice;
 pub use base::{PyObject, ToPyType, FromPyType, PyState, PyIterator};
 pub use ffi::{PythonCAPI, PyObjectRaw};
 pub use base::PyError;
@@ -19,7 +25,7 @@ macro_rules! prim_pytype (
       }
     }
 
-    impl FromPyType for $base_type {
+    impl<'a> FromPyType<'a> for $base_type {
       fn from_py_object(state : &PyState, py_object : PyObject) -> Result<$base_type, PyError>  {
         unsafe {
           if !py_object.raw.is_null() && state.$check(py_object.raw) > 0 {
@@ -63,10 +69,10 @@ macro_rules! tuple_pytype ({$length:expr,$(($refN:ident, $n:tt, $T:ident)),+} =>
     }
   }
 
-  impl<$($T:FromPyType),+> FromPyType for ($($T,)+) {
-    fn from_py_object(state : &PyState, py_object : PyObject) -> Result<($($T,)+), PyError>  {
+  impl<'a, $($T:FromPyType<'a>),+> FromPyType<'a> for ($($T,)+) {
+    fn from_py_object(state : &'a PyState, py_object : PyObject<'a>) -> Result<($($T,)+), PyError>  {
       unsafe {
-        if py_object.raw.is_null() && state.PyTuple_Check(py_object.raw) > 0 {
+        if py_object.raw.is_null() || state.PyTuple_Check(py_object.raw) == 0 {
           Err(PyError::FromTypeConversionError)
         } else {
           let raw = py_object.raw;
@@ -75,6 +81,7 @@ macro_rules! tuple_pytype ({$length:expr,$(($refN:ident, $n:tt, $T:ident)),+} =>
             //TODO is there a better way to do this check?
             let no_null = vec!($(!$refN.is_null(), ) +).iter().all(|&x| x);
             if no_null {
+              $(state.Py_IncRef($refN);)+
               $(let $refN = PyObject::new(state, $refN);)+
               $(let $refN = try!(state.from_py_object::<$T>($refN));)+
               Ok(($($refN,)+))
@@ -90,48 +97,106 @@ macro_rules! tuple_pytype ({$length:expr,$(($refN:ident, $n:tt, $T:ident)),+} =>
   }
 ));
 
+/// Build a python list out of the given items.
+fn list_to_py_object<'a, 'b, T, I>(state: &'a PyState,
+                                   len: usize,
+                                   items: I)
+                                   -> Result<PyObject<'a>, PyError>
+    where T: ToPyType + 'b,
+          I: Iterator<Item = &'b T>
+{
+    unsafe {
+        let raw = state.PyList_New(len as size_t);
+        if raw.is_null() {
+            return Err(PyError::ToTypeConversionError);
+        }
+        let list = PyObject::new(state, raw);
+        for (i, item) in items.enumerate() {
+            let pyitem = try!(item.to_py_object(state));
+            state.Py_IncRef(pyitem.raw);
+            state.PyList_SetItem(list.raw, i as size_t, pyitem.raw);
+        }
+        Ok(list)
+    }
+}
+
 impl<T: ToPyType> ToPyType for Vec<T> {
     fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
-        unsafe {
-            let raw = state.PyList_New(self.len() as size_t);
-            for (i, item) in self.iter().enumerate() {
-                let pyitem = try!(item.to_py_object(state));
-                state.Py_IncRef(pyitem.raw);
-                state.PyList_SetItem(raw, i as size_t, pyitem.raw);
-            }
-            if !raw.is_null() {
-                Ok(PyObject::new(state, raw))
-            } else {
-                Err(PyError::ToTypeConversionError)
-            }
-        }
+        list_to_py_object(state, self.len(), self.iter())
     }
 }
 
-impl<T: FromPyType> FromPyType for Vec<T> {
-    fn from_py_object(state: &PyState, py_object: PyObject) -> Result<Vec<T>, PyError> {
-        unsafe {
-            if !py_object.raw.is_null() && state.PyList_Check(py_object.raw) > 0 {
-                let raw = py_object.raw;
-                let size = state.PyList_Size(raw) as usize;
-                let mut v = Vec::with_capacity(size);
-                for i in 0..size {
-                    let rawitem = state.PyList_GetItem(raw, i as size_t);
-                    if rawitem.is_null() {
-                        return Err(PyError::FromTypeConversionError);
-                    }
-                    let pyitem = PyObject::new(state, rawitem);
-                    let item = try!(state.from_py_object::<T>(pyitem));
-                    v.push(item);
-                }
-                Ok(v)
-            } else {
+/// Extract the items of any python sequence or iterable.
+///
+/// Lists and tuples are read directly by index, everything else goes through
+/// the python iterator protocol. `str` and `unicode` are refused even though
+/// python can iterate over them, as that is almost never what is meant.
+fn sequence_from_py_object<'a, T, C>(state: &'a PyState, py_object: PyObject<'a>) -> Result<C, PyError>
+    where T: FromPyType<'a>,
+          C: FromIterator<T>
+{
+    unsafe {
+        let raw = py_object.raw;
+        if raw.is_null() || state.PyString_Check(raw) > 0 || state.PyUnicode_Check(raw) > 0 {
+            Err(PyError::FromTypeConversionError)
+        } else if state.PyList_Check(raw) > 0 {
+            let size = state.PyList_Size(raw) as usize;
+            (0..size)
+                .map(|i| borrowed_from_py_object(state, state.PyList_GetItem(raw, i as size_t)))
+                .collect()
+        } else if state.PyTuple_Check(raw) > 0 {
+            let size = state.PyTuple_Size(raw) as usize;
+            (0..size)
+                .map(|i| borrowed_from_py_object(state, state.PyTuple_GetItem(raw, i as size_t)))
+                .collect()
+        } else {
+            let py_iter = state.PyObject_GetIter(raw);
+            if py_iter.is_null() {
+                state.PyErr_Clear();
                 Err(PyError::FromTypeConversionError)
+            } else {
+                try!(PyIterator::new(PyObject::new(state, py_iter))).collect()
             }
         }
     }
 }
 
+/// Convert an item borrowed from a container, taking a new reference to it.
+unsafe fn borrowed_from_py_object<'a, T: FromPyType<'a>>(state: &'a PyState,
+                                                         raw: *mut PyObjectRaw)
+                                                         -> Result<T, PyError> {
+    if raw.is_null() {
+        return Err(PyError::FromTypeConversionError);
+    }
+    state.Py_IncRef(raw);
+    state.from_py_object::<T>(PyObject::new(state, raw))
+}
+
+impl<'a, T: FromPyType<'a>> FromPyType<'a> for Vec<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<Vec<T>, PyError> {
+        sequence_from_py_object(state, py_object)
+    }
+}
+
+impl<T: ToPyType> ToPyType for VecDeque<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        list_to_py_object(state, self.len(), self.iter())
+    }
+}
+
+impl<'a, T: FromPyType<'a>> FromPyType<'a> for VecDeque<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<VecDeque<T>, PyError> {
+        sequence_from_py_object(state, py_object)
+    }
+}
+
+impl<'a, T: FromPyType<'a>, const N: usize> FromPyType<'a> for [T; N] {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<[T; N], PyError> {
+        let items: Vec<T> = try!(sequence_from_py_object(state, py_object));
+        <[T; N]>::try_from(items).map_err(|_| PyError::FromTypeConversionError)
+    }
+}
+
 tuple_pytype!(1,(ref0, 0, A));
 tuple_pytype!(2,(ref0, 0, A),(ref1, 1, B));
 tuple_pytype!(3, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C));
@@ -146,6 +211,65 @@ tuple_pytype!(8, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
   (ref4, 4, E), (ref5, 5, F), (ref6, 6, G),(ref7, 7, H));
 tuple_pytype!(9, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
   (ref4, 4, E), (ref5, 5, F),(ref6, 6, G),(ref7, 7, H),(ref8, 8, I));
+tuple_pytype!(10, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J));
+tuple_pytype!(11, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K));
+tuple_pytype!(12, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K), (ref11, 11, L));
+tuple_pytype!(13, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K), (ref11, 11, L), (ref12, 12, M));
+tuple_pytype!(14, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K), (ref11, 11, L), (ref12, 12, M), (ref13, 13, N));
+tuple_pytype!(15, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K), (ref11, 11, L), (ref12, 12, M), (ref13, 13, N),
+  (ref14, 14, O));
+tuple_pytype!(16, (ref0, 0, A), (ref1, 1, B), (ref2, 2, C), (ref3, 3, D),
+  (ref4, 4, E), (ref5, 5, F), (ref6, 6, G), (ref7, 7, H), (ref8, 8, I),
+  (ref9, 9, J), (ref10, 10, K), (ref11, 11, L), (ref12, 12, M), (ref13, 13, N),
+  (ref14, 14, O), (ref15, 15, P));
+
+impl<T: ToPyType> ToPyType for [T] {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        list_to_py_object(state, self.len(), self.iter())
+    }
+}
+
+impl<T: ToPyType, const N: usize> ToPyType for [T; N] {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        list_to_py_object(state, N, self.iter())
+    }
+}
+
+impl<T: ToPyType + ?Sized> ToPyType for &T {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        (**self).to_py_object(state)
+    }
+}
+
+impl<T: ToPyType + ?Sized> ToPyType for Box<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        (**self).to_py_object(state)
+    }
+}
+
+impl<T: ToPyType + ?Sized> ToPyType for Rc<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        (**self).to_py_object(state)
+    }
+}
+
+impl<T: ToPyType + ?Sized> ToPyType for Arc<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        (**self).to_py_object(state)
+    }
+}
 
 impl ToPyType for String {
     fn to_py_object<'a, 'b>(&'b self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
@@ -161,7 +285,7 @@ impl ToPyType for String {
     }
 }
 
-impl FromPyType for String {
+impl<'a> FromPyType<'a> for String {
     fn from_py_object(state: &PyState, py_object: PyObject) -> Result<String, PyError> {
         unsafe {
             if !py_object.raw.is_null() && state.PyString_Check(py_object.raw) > 0 {
@@ -188,6 +312,45 @@ impl ToPyType for str {
     }
 }
 
+/// Build a python `str` holding exactly `bytes`, which may contain any value.
+pub fn bytes_to_py_object<'a>(state: &'a PyState, bytes: &[u8]) -> Result<PyObject<'a>, PyError> {
+    unsafe {
+        let raw = state.PyString_FromStringAndSize(bytes.as_ptr() as *const c_char,
+                                                   bytes.len() as size_t);
+        if !raw.is_null() && state.PyString_Check(raw) > 0 {
+            Ok(PyObject::new(state, raw))
+        } else {
+            Err(PyError::ToTypeConversionError)
+        }
+    }
+}
+
+/// Copy the raw contents of a python `str`.
+pub fn bytes_from_py_object(state: &PyState, py_object: &PyObject) -> Result<Vec<u8>, PyError> {
+    unsafe {
+        if !py_object.raw.is_null() && state.PyString_Check(py_object.raw) > 0 {
+            let data = state.PyString_AsString(py_object.raw) as *const u8;
+            let size = state.PyString_Size(py_object.raw) as usize;
+            Ok(slice::from_raw_parts(data, size).to_vec())
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+/// Read a UTF-8 `str`, or a `unicode` encoded as UTF-8.
+pub fn text_from_py_object(state: &PyState, py_object: &PyObject) -> Result<String, PyError> {
+    let bytes = unsafe {
+        if !py_object.raw.is_null() && state.PyUnicode_Check(py_object.raw) > 0 {
+            let encoded = try!(py_object.call_func("encode", ("utf-8".to_string(),)));
+            try!(bytes_from_py_object(state, &encoded))
+        } else {
+            try!(bytes_from_py_object(state, py_object))
+        }
+    };
+    String::from_utf8(bytes).map_err(|_| PyError::StringConversionError)
+}
+
 /// Structure that represents an empty tuple in python
 pub struct NoArgs;
 
@@ -197,16 +360,32 @@ impl ToPyType for NoArgs {
     }
 }
 
-impl FromPyType for NoArgs {
+impl<'a> FromPyType<'a> for NoArgs {
     fn from_py_object(_: &PyState, _: PyObject) -> Result<NoArgs, PyError> {
         Ok(NoArgs)
     }
 }
 
+/// A `PyObject` passes through untouched, taking a new reference, so raw
+/// python objects can be mixed with typed values in tuples, vectors and maps.
+impl<'b> ToPyType for PyObject<'b> {
+    fn to_py_object<'a>(&'a self, _: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        Ok(self.clone())
+    }
+}
+
+impl<'a> FromPyType<'a> for PyObject<'a> {
+    fn from_py_object(_: &'a PyState, py_object: PyObject<'a>) -> Result<PyObject<'a>, PyError> {
+        Ok(py_object)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use base::PyState;
-    use super::{ToPyType, FromPyType, NoArgs};
+    use std::collections::VecDeque;
+    use base::{PyState, PyError};
+    use mapping::OrderedMap;
+    use super::{ToPyType, FromPyType, NoArgs, PyObject};
     macro_rules! try_or_panic (
       ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
   );
@@ -251,6 +430,62 @@ mod test {
     tuple_to_py_object_and_back!((1,2,3,4), (isize,isize,isize,isize), to_and_from_tuple4);
     tuple_to_py_object_and_back!((1,2,3,4,5), (isize,isize,isize,isize,isize), to_and_from_tuple5);
     tuple_to_py_object_and_back!((1,2,3,4,5,6), (isize,isize,isize,isize,isize,isize), to_and_from_tuple6);
+    tuple_to_py_object_and_back!((1,2,3,4,5,6,7,8,9,10,11,12),
+      (isize,isize,isize,isize,isize,isize,isize,isize,isize,isize,isize,isize), to_and_from_tuple12);
+
+    #[test]
+    fn to_and_from_tuple16() {
+        let py = PyState::new();
+        let value = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, "last".to_string());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<(isize, isize, isize, isize, isize,
+                                                          isize, isize, isize, isize, isize,
+                                                          isize, isize, isize, isize, isize,
+                                                          String)>(py_object));
+        assert_eq!(returned.0, 0);
+        assert_eq!(returned.14, 14);
+        assert_eq!(returned.15, "last");
+    }
+
+    #[test]
+    fn slices_and_arrays_to_list() {
+        let py = PyState::new();
+        let array = [1, 2, 3];
+        let py_object = try_or_panic!(array.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{[1, 2, 3]}");
+        let slice: &[isize] = &array[1..];
+        let py_object = try_or_panic!(slice.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{[2, 3]}");
+    }
+
+    #[test]
+    fn pass_arguments_by_reference() {
+        let py = PyState::new();
+        let values = vec![3, 1, 2];
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let sorted: Vec<isize> = try_or_panic!(builtins.call_func_with_ret("sorted",
+                                                                           (&values[..],)));
+        assert_eq!(sorted, vec![1, 2, 3]);
+        let string: String = try_or_panic!(builtins.call_func_with_ret("str", (&"abc",)));
+        assert_eq!(string, "abc");
+        assert_eq!(values, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn smart_pointers_to_py_object() {
+        use std::rc::Rc;
+        use std::sync::Arc;
+        let py = PyState::new();
+        let boxed = Box::new(1.5f64);
+        let py_object = try_or_panic!(boxed.to_py_object(&py));
+        assert_eq!(try_or_panic!(py.from_py_object::<f64>(py_object)), 1.5);
+        let rc = Rc::new("rc".to_string());
+        let py_object = try_or_panic!(rc.to_py_object(&py));
+        assert_eq!(try_or_panic!(py.from_py_object::<String>(py_object)), "rc");
+        let arc: Arc<[isize]> = Arc::from(vec![1, 2]);
+        let py_object = try_or_panic!(arc.to_py_object(&py));
+        assert_eq!(try_or_panic!(py.from_py_object::<Vec<isize>>(py_object)), vec![1, 2]);
+    }
 
     #[test]
     fn to_and_from_list() {
@@ -261,6 +496,99 @@ mod test {
         assert_eq!(returned, val);
     }
 
+    #[test]
+    fn tuple_to_vec() {
+        let py = PyState::new();
+        let value = (1, 2, 3);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Vec<isize>>(py_object));
+        assert_eq!(returned, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn iterable_to_vec() {
+        let py = PyState::new();
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let range = try_or_panic!(builtins.call_func("xrange", (4,)));
+        let returned = try_or_panic!(py.from_py_object::<Vec<isize>>(range));
+        assert_eq!(returned, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn deque_to_vec_deque() {
+        let py = PyState::new();
+        let collections = try_or_panic!(py.get_module("collections"));
+        let deque = try_or_panic!(collections.call_func("deque", (vec![1, 2, 3],)));
+        let returned = try_or_panic!(py.from_py_object::<VecDeque<isize>>(deque));
+        assert_eq!(returned, vec![1, 2, 3].into_iter().collect::<VecDeque<isize>>());
+    }
+
+    #[test]
+    fn vec_deque_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = vec![1, 2, 3].into_iter().collect::<VecDeque<isize>>();
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<VecDeque<isize>>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn sequence_to_array() {
+        let py = PyState::new();
+        let value = (1f64, 2f64, 3f64);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<[f64; 3]>(py_object));
+        assert_eq!(returned, [1f64, 2f64, 3f64]);
+    }
+
+    #[test]
+    fn sequence_to_array_of_wrong_length_should_err() {
+        let py = PyState::new();
+        let value = vec![1, 2];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let result = py.from_py_object::<[isize; 3]>(py_object);
+        match result {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn string_to_vec_should_err() {
+        let py = PyState::new();
+        let value = "abc";
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let result = py.from_py_object::<Vec<String>>(py_object);
+        match result {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn unicode_to_vec_should_err() {
+        let py = PyState::new();
+        let value = "abc";
+        let encoded = try_or_panic!(value.to_py_object(&py));
+        let unicode = try_or_panic!(encoded.call_func("decode", ("ascii",)));
+        match py.from_py_object::<Vec<PyObject>>(unicode) {
+            Err(PyError::FromTypeConversionError) => (),
+            result => panic!("expected a conversion error but got {:?}", result),
+        };
+    }
+
+    #[test]
+    fn failing_iterable_to_vec_should_err() {
+        let py = PyState::new();
+        let codecs = try_or_panic!(py.get_module("codecs"));
+        let args = (vec!["\u{e9}".to_string()], "ascii".to_string());
+        let decoded = try_or_panic!(codecs.call_func("iterdecode", args));
+        match py.from_py_object::<Vec<String>>(decoded) {
+            Err(PyError::PyException(s)) => assert!(s.starts_with("UnicodeDecodeError")),
+            x => panic!("should have raised but got {:?}", x),
+        };
+    }
+
     #[test]
     fn mixed_convert() {
         let py = PyState::new();
@@ -285,6 +613,19 @@ mod test {
         };
     }
 
+    #[test]
+    fn list_to_tuple_should_err_without_python_error() {
+        let py = PyState::new();
+        let value = vec![1isize, 2];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        match py.from_py_object::<(isize, isize)>(py_object) {
+            Err(PyError::FromTypeConversionError) => (),
+            result => panic!("expected a conversion error but got {:?}", result),
+        };
+        // No python error is left behind for the next call to report.
+        try_or_panic!(py.get_module("math"));
+    }
+
     #[test]
     fn tuple_to_float_should_err() {
         let py = PyState::new();
@@ -323,4 +664,70 @@ mod test {
         let py_object = try_or_panic!(value.to_py_object(&py));
         let _ = try_or_panic!(py.from_py_object::<NoArgs>(py_object));
     }
+
+    #[test]
+    fn py_object_inside_tuple_argument() {
+        let py = PyState::new();
+        let math = try_or_panic!(py.get_module("math"));
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let args = (math, "pi".to_string());
+        let pi: f64 = try_or_panic!(builtins.call_func_with_ret("getattr", args));
+        assert_eq!(pi, ::std::f64::consts::PI);
+    }
+
+    #[test]
+    fn py_object_inside_extracted_tuple() {
+        let py = PyState::new();
+        let source = concat!("class Thing(object):\n",
+                             "    name = 'thing'\n",
+                             "def pair():\n",
+                             "    return (3, Thing())\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_passthrough", source));
+        let (count, thing): (isize, PyObject) =
+            try_or_panic!(module.call_func_with_ret("pair", NoArgs));
+        assert_eq!(count, 3);
+        let name: String = try_or_panic!(thing.get_member("name"));
+        assert_eq!(&name, "thing");
+    }
+
+    #[test]
+    fn extracting_tuple_keeps_item_references() {
+        let py = PyState::new();
+        let source = concat!("import sys\n",
+                             "item = object()\n",
+                             "pair = (item, 1)\n",
+                             "def refcount():\n",
+                             "    return sys.getrefcount(item)\n");
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_tuple_refs", source));
+        let before: isize = try_or_panic!(module.call_func_with_ret("refcount", NoArgs));
+        for _ in 0..3 {
+            let pair = try_or_panic!(module.get_member_obj("pair"));
+            let (item, count): (PyObject, isize) = try_or_panic!(py.from_py_object(pair));
+            assert_eq!(count, 1);
+            drop(item);
+        }
+        let after: isize = try_or_panic!(module.call_func_with_ret("refcount", NoArgs));
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn py_objects_inside_vec_and_map() {
+        let py = PyState::new();
+        let math = try_or_panic!(py.get_module("math"));
+        let items = vec![try_or_panic!(1isize.to_py_object(&py)), math.clone(), math];
+        let py_object = try_or_panic!(items.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Vec<PyObject>>(py_object));
+        assert_eq!(returned.len(), 3);
+        assert_eq!(try_or_panic!(returned[0].str()), "1");
+        let sqrt: f64 = try_or_panic!(returned[2].call_func_with_ret("sqrt", (4.0,)));
+        assert_eq!(sqrt, 2.0);
+
+        let map = OrderedMap(vec![("count".to_string(), returned[0].clone()),
+                                  ("module".to_string(), returned[1].clone())]);
+        let py_object = try_or_panic!(map.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<OrderedMap<String, PyObject>>(py_object));
+        assert_eq!(&returned.0[1].0, "module");
+        let pi: f64 = try_or_panic!(returned.0[1].1.get_member("pi"));
+        assert_eq!(pi, ::std::f64::consts::PI);
+    }
 }
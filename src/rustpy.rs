@@ -28,15 +28,99 @@
 //! }
 //! ```
 //!
+//! Structs and enums can derive both traits. Named fields map to a python
+//! `dict`, see the [`derive`](../rustpy_derive/index.html) documentation for
+//! the other representations and the `#[rustpy(...)]` field attributes.
+//!
+//! ```rust
+//! extern crate rustpy;
+//! use rustpy::{ToPyType, FromPyType, PyState};
+//!
+//! #[derive(Debug, PartialEq, ToPyType, FromPyType)]
+//! struct Point {
+//!     x: isize,
+//!     #[rustpy(rename = "height")]
+//!     y: isize,
+//! }
+//!
+//! fn main() {
+//! let py = PyState::new();
+//! let point = Point { x: 1, y: 2 };
+//! let py_point = point.to_py_object(&py).unwrap();
+//! assert_eq!(format!("{:?}", py_point), "PyObject{{'x': 1, 'height': 2}}");
+//! assert_eq!(py.from_py_object::<Point>(py_point).unwrap(), point);
+//! }
+//! ```
+//!
+//! Tuple structs are read from a tuple of the exact length, so their fields
+//! cannot have a default:
+//!
+//! ```rust,compile_fail
+//! extern crate rustpy;
+//! use rustpy::FromPyType;
+//!
+//! #[derive(FromPyType)]
+//! struct Range(isize, #[rustpy(default)] isize); // error: only on named fields
+//! # fn main() {}
+//! ```
+//!
 #![crate_type = "lib"]
 
 extern crate libc;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "indexmap")]
+extern crate indexmap;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde as serde_crate;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+extern crate rustpy_derive;
+// Lets the derived impls name `::rustpy` from inside this crate too.
+extern crate self as rustpy;
 
-pub use base::{ToPyType, FromPyType, PyState, PyObject, PyObjectRaw, PyError, PyIterator};
+pub use base::{ToPyType, FromPyType, PyState, SubInterpreter, PyObject, PyObjectRaw, PyError,
+               PyIterator};
 pub use primtypes::NoArgs;
+pub use sets::FrozenSet;
+pub use numbers::{Complex64, Decimal, Fraction};
+pub use mapping::OrderedMap;
+pub use types::{PyDowncast, PyList, PyTuple, PyDict, PyStr, PyModule};
+pub use buffer::{Array, Element, PyBuffer};
+pub use owned::OwnedPyObject;
+pub use builder::PyStateBuilder;
+pub use output::CapturedOutput;
+pub use rustpy_derive::{ToPyType, FromPyType};
+#[cfg(feature = "serde")]
+pub use serde::Serde;
+#[cfg(feature = "numpy")]
+pub use numpy::NdArray;
 
 mod base;
 mod primtypes;
+mod sets;
+mod numbers;
+mod mapping;
+mod datetime;
+mod path;
+mod types;
+mod buffer;
+mod pickle;
+mod owned;
+mod builder;
+mod output;
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "numpy")]
+mod numpy;
+#[doc(hidden)]
+pub mod derive;
 mod ffi;
@@ -0,0 +1,791 @@
+//! Conversions for any serde `Serialize` or `Deserialize` type, enabled with
+//! the `serde` feature.
+//!
+//! Values use the same python representation as `#[derive(ToPyType)]`:
+//! structs and maps become a `dict`, sequences a `list`, tuples a `tuple`,
+//! options and unit values `None`, and enums are externally tagged.
+//!
+//! ```rust
+//! extern crate rustpy;
+//! use rustpy::{PyState, Serde};
+//!
+//! fn main() {
+//! let py = PyState::new();
+//! let json = py.get_module("json").unwrap();
+//! let value = vec![Some(1isize), None];
+//! let text: String = json.call_func_with_ret("dumps", (Serde(value),)).unwrap();
+//! assert_eq!(text, "[1, null]");
+//! }
+//! ```
+
+use std::fmt::Display;
+use std::vec;
+use libc::{c_long, c_ulonglong, size_t};
+use serde_crate::ser::{self, Serialize};
+use serde_crate::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde_crate::de::value::StringDeserializer;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::{PythonCAPI, PyObjectRaw};
+use primtypes::{bytes_to_py_object, bytes_from_py_object, text_from_py_object};
+use mapping::mapping_entries;
+use derive::{none, new_dict, new_tuple, tagged_object, tuple_items, variant};
+
+impl ser::Error for PyError {
+    fn custom<T: Display>(msg: T) -> PyError {
+        PyError::Serde(msg.to_string())
+    }
+}
+
+impl de::Error for PyError {
+    fn custom<T: Display>(msg: T) -> PyError {
+        PyError::Serde(msg.to_string())
+    }
+}
+
+/// Wrapper giving any serde type `ToPyType` and `FromPyType`, so it can be
+/// passed to `call_func` or read back with `from_py_object`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Serde<T>(pub T);
+
+impl<T: Serialize> ToPyType for Serde<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        to_py_object(state, &self.0)
+    }
+}
+
+impl<'a, T: DeserializeOwned> FromPyType<'a> for Serde<T> {
+    fn from_py_object(_state: &PyState, py_object: PyObject) -> Result<Serde<T>, PyError> {
+        from_py_object(py_object).map(Serde)
+    }
+}
+
+/// Convert any `Serialize` value to a python object.
+pub fn to_py_object<'a, T>(state: &'a PyState, value: &T) -> Result<PyObject<'a>, PyError>
+    where T: Serialize + ?Sized
+{
+    value.serialize(Serializer::new(state))
+}
+
+/// Build any `Deserialize` value out of a python object.
+pub fn from_py_object<T: DeserializeOwned>(py_object: PyObject) -> Result<T, PyError> {
+    T::deserialize(Deserializer::new(py_object))
+}
+
+/// Take ownership of a new reference returned by the python api.
+unsafe fn new_object<'a>(state: &'a PyState,
+                         raw: *mut PyObjectRaw)
+                         -> Result<PyObject<'a>, PyError> {
+    if raw.is_null() {
+        try!(state.get_result_exception());
+        Err(PyError::ToTypeConversionError)
+    } else {
+        Ok(PyObject::new(state, raw))
+    }
+}
+
+fn set_item(dict: &PyObject, key: &PyObject, value: &PyObject) -> Result<(), PyError> {
+    unsafe {
+        if dict.state.PyDict_SetItem(dict.raw, key.raw, value.raw) != 0 {
+            try!(dict.state.get_result_exception());
+            Err(PyError::ToTypeConversionError)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Serde serializer producing a python object.
+pub struct Serializer<'a> {
+    state: &'a PyState,
+}
+
+impl<'a> Serializer<'a> {
+    pub fn new(state: &'a PyState) -> Serializer<'a> {
+        Serializer { state }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<PyObject<'a>, PyError> {
+        unsafe { new_object(self.state, self.state.PyBool_FromLong(v as c_long)) }
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<PyObject<'a>, PyError> {
+        unsafe { new_object(self.state, self.state.PyInt_FromLong(v as c_long)) }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<PyObject<'a>, PyError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<PyObject<'a>, PyError> {
+        if v <= i64::MAX as u64 {
+            self.serialize_i64(v as i64)
+        } else {
+            unsafe {
+                new_object(self.state,
+                           self.state.PyLong_FromUnsignedLongLong(v as c_ulonglong))
+            }
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<PyObject<'a>, PyError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<PyObject<'a>, PyError> {
+        unsafe { new_object(self.state, self.state.PyFloat_FromDouble(v)) }
+    }
+
+    fn serialize_char(self, v: char) -> Result<PyObject<'a>, PyError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<PyObject<'a>, PyError> {
+        bytes_to_py_object(self.state, v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<PyObject<'a>, PyError> {
+        bytes_to_py_object(self.state, v)
+    }
+
+    fn serialize_none(self) -> Result<PyObject<'a>, PyError> {
+        Ok(none(self.state))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<PyObject<'a>, PyError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<PyObject<'a>, PyError> {
+        Ok(none(self.state))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<PyObject<'a>, PyError> {
+        Ok(none(self.state))
+    }
+
+    fn serialize_unit_variant(self,
+                              _name: &'static str,
+                              _index: u32,
+                              variant: &'static str)
+                              -> Result<PyObject<'a>, PyError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self,
+                                   _name: &'static str,
+                                   value: &T)
+                                   -> Result<PyObject<'a>, PyError>
+        where T: Serialize + ?Sized
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self,
+                                    _name: &'static str,
+                                    _index: u32,
+                                    variant: &'static str,
+                                    value: &T)
+                                    -> Result<PyObject<'a>, PyError>
+        where T: Serialize + ?Sized
+    {
+        let py_value = try!(to_py_object(self.state, value));
+        tagged_object(self.state, variant, &py_value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'a>, PyError> {
+        Ok(SeqSerializer::new(self.state, false, len.unwrap_or(0), None))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>, PyError> {
+        Ok(SeqSerializer::new(self.state, true, len, None))
+    }
+
+    fn serialize_tuple_struct(self,
+                              _name: &'static str,
+                              len: usize)
+                              -> Result<SeqSerializer<'a>, PyError> {
+        Ok(SeqSerializer::new(self.state, true, len, None))
+    }
+
+    fn serialize_tuple_variant(self,
+                               _name: &'static str,
+                               _index: u32,
+                               variant: &'static str,
+                               len: usize)
+                               -> Result<SeqSerializer<'a>, PyError> {
+        Ok(SeqSerializer::new(self.state, true, len, Some(variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a>, PyError> {
+        MapSerializer::new(self.state, None)
+    }
+
+    fn serialize_struct(self,
+                        _name: &'static str,
+                        _len: usize)
+                        -> Result<MapSerializer<'a>, PyError> {
+        MapSerializer::new(self.state, None)
+    }
+
+    fn serialize_struct_variant(self,
+                                _name: &'static str,
+                                _index: u32,
+                                variant: &'static str,
+                                _len: usize)
+                                -> Result<MapSerializer<'a>, PyError> {
+        MapSerializer::new(self.state, Some(variant))
+    }
+}
+
+/// Collects the items of a `list` or `tuple`, wrapped in a tagged `dict` for
+/// enum variants.
+pub struct SeqSerializer<'a> {
+    state: &'a PyState,
+    tuple: bool,
+    items: Vec<PyObject<'a>>,
+    variant: Option<&'static str>,
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn new(state: &'a PyState,
+           tuple: bool,
+           len: usize,
+           variant: Option<&'static str>)
+           -> SeqSerializer<'a> {
+        SeqSerializer {
+            state,
+            tuple,
+            items: Vec::with_capacity(len),
+            variant,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        let item = try!(to_py_object(self.state, value));
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PyObject<'a>, PyError> {
+        let state = self.state;
+        let sequence = unsafe {
+            let len = self.items.len();
+            if self.tuple {
+                let tuple = try!(new_tuple(state, len));
+                for (i, item) in self.items.iter().enumerate() {
+                    state.Py_IncRef(item.raw);
+                    state.PyTuple_SetItem(tuple.raw, i as size_t, item.raw);
+                }
+                tuple
+            } else {
+                let list = try!(new_object(state, state.PyList_New(len as size_t)));
+                for (i, item) in self.items.iter().enumerate() {
+                    state.Py_IncRef(item.raw);
+                    state.PyList_SetItem(list.raw, i as size_t, item.raw);
+                }
+                list
+            }
+        };
+        match self.variant {
+            Some(variant) => tagged_object(state, variant, &sequence),
+            None => Ok(sequence),
+        }
+    }
+}
+
+impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+/// Fills a `dict`, wrapped in a tagged `dict` for enum variants.
+pub struct MapSerializer<'a> {
+    dict: PyObject<'a>,
+    key: Option<PyObject<'a>>,
+    variant: Option<&'static str>,
+}
+
+impl<'a> MapSerializer<'a> {
+    fn new(state: &'a PyState, variant: Option<&'static str>) -> Result<MapSerializer<'a>, PyError> {
+        Ok(MapSerializer {
+            dict: try!(new_dict(state)),
+            key: None,
+            variant,
+        })
+    }
+
+    fn finish(self) -> Result<PyObject<'a>, PyError> {
+        match self.variant {
+            Some(variant) => tagged_object(self.dict.state, variant, &self.dict),
+            None => Ok(self.dict),
+        }
+    }
+}
+
+impl<'a> ser::SerializeMap for MapSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), PyError> {
+        self.key = Some(try!(to_py_object(self.dict.state, key)));
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PyError> {
+        let key = try!(self.key.take().ok_or(PyError::ToTypeConversionError));
+        let value = try!(to_py_object(self.dict.state, value));
+        set_item(&self.dict, &key, &value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for MapSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), PyError>
+        where T: Serialize + ?Sized
+    {
+        let key = try!(bytes_to_py_object(self.dict.state, key.as_bytes()));
+        let value = try!(to_py_object(self.dict.state, value));
+        set_item(&self.dict, &key, &value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
+    type Ok = PyObject<'a>;
+    type Error = PyError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), PyError>
+        where T: Serialize + ?Sized
+    {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<PyObject<'a>, PyError> {
+        self.finish()
+    }
+}
+
+/// Serde deserializer reading from a python object.
+///
+/// Understands `None`, `bool`, `int`, `long`, `float`, `str`, `unicode`,
+/// `list`, `tuple` and `dict`.
+pub struct Deserializer<'a> {
+    py_object: PyObject<'a>,
+}
+
+impl<'a> Deserializer<'a> {
+    pub fn new(py_object: PyObject<'a>) -> Deserializer<'a> {
+        Deserializer { py_object }
+    }
+
+    /// Items of a `list` or `tuple`.
+    fn items(&self) -> Result<Vec<PyObject<'a>>, PyError> {
+        let state = self.py_object.state;
+        let raw = self.py_object.raw;
+        let len = unsafe {
+            if state.PyTuple_Check(raw) > 0 {
+                state.PyTuple_Size(raw)
+            } else {
+                state.PyList_Size(raw)
+            }
+        };
+        tuple_items(&self.py_object, len as usize)
+    }
+
+    /// Read a python `long`, which may not fit in an `i64`.
+    unsafe fn visit_long<'de, V: Visitor<'de>>(&self, visitor: V) -> Result<V::Value, PyError> {
+        let state = self.py_object.state;
+        let value = state.PyLong_AsLongLong(self.py_object.raw);
+        if state.PyErr_Occurred().is_null() {
+            return visitor.visit_i64(value);
+        }
+        state.PyErr_Clear();
+        let value = state.PyLong_AsUnsignedLongLong(self.py_object.raw);
+        try!(state.get_result_exception());
+        visitor.visit_u64(value)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = PyError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PyError> {
+        let state = self.py_object.state;
+        let raw = self.py_object.raw;
+        if raw.is_null() {
+            return Err(PyError::NullPyObject);
+        }
+        unsafe {
+            if self.py_object.is_none() {
+                visitor.visit_unit()
+            } else if state.PyBool_Check(raw) > 0 {
+                visitor.visit_bool(state.PyObject_IsTrue(raw) > 0)
+            } else if state.PyInt_Check(raw) > 0 {
+                visitor.visit_i64(state.PyInt_AsLong(raw))
+            } else if state.PyLong_Check(raw) > 0 {
+                self.visit_long(visitor)
+            } else if state.PyFloat_Check(raw) > 0 {
+                visitor.visit_f64(state.PyFloat_AsDouble(raw))
+            } else if state.PyString_Check(raw) > 0 || state.PyUnicode_Check(raw) > 0 {
+                visitor.visit_string(try!(text_from_py_object(state, &self.py_object)))
+            } else if state.PyList_Check(raw) > 0 || state.PyTuple_Check(raw) > 0 {
+                let items = try!(self.items());
+                visitor.visit_seq(SeqAccess { items: items.into_iter() })
+            } else if state.PyDict_Check(raw) > 0 {
+                let entries = try!(mapping_entries(&self.py_object));
+                visitor.visit_map(MapAccess {
+                    entries: entries.into_iter(),
+                    value: None,
+                })
+            } else {
+                Err(PyError::FromTypeConversionError)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PyError> {
+        if self.py_object.is_none() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PyError> {
+        let state = self.py_object.state;
+        if unsafe { state.PyString_Check(self.py_object.raw) } > 0 {
+            visitor.visit_byte_buf(try!(bytes_from_py_object(state, &self.py_object)))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PyError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self,
+                                     _name: &'static str,
+                                     visitor: V)
+                                     -> Result<V::Value, PyError>
+        where V: Visitor<'de>
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self,
+                           _name: &'static str,
+                           _variants: &'static [&'static str],
+                           visitor: V)
+                           -> Result<V::Value, PyError>
+        where V: Visitor<'de>
+    {
+        let (tag, payload) = try!(variant(&self.py_object));
+        visitor.visit_enum(EnumAccess { tag, payload })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string unit unit_struct
+        seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccess<'a> {
+    items: vec::IntoIter<PyObject<'a>>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a> {
+    type Error = PyError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, PyError>
+        where T: DeserializeSeed<'de>
+    {
+        match self.items.next() {
+            Some(item) => seed.deserialize(Deserializer::new(item)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess<'a> {
+    entries: vec::IntoIter<(PyObject<'a>, PyObject<'a>)>,
+    value: Option<PyObject<'a>>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a> {
+    type Error = PyError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, PyError>
+        where K: DeserializeSeed<'de>
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, PyError>
+        where V: DeserializeSeed<'de>
+    {
+        let value = try!(self.value.take().ok_or(PyError::FromTypeConversionError));
+        seed.deserialize(Deserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess<'a> {
+    tag: String,
+    payload: Option<PyObject<'a>>,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for EnumAccess<'a> {
+    type Error = PyError;
+    type Variant = VariantAccess<'a>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantAccess<'a>), PyError>
+        where V: DeserializeSeed<'de>
+    {
+        let tag: StringDeserializer<PyError> = self.tag.into_deserializer();
+        let value = try!(seed.deserialize(tag));
+        Ok((value, VariantAccess { payload: self.payload }))
+    }
+}
+
+struct VariantAccess<'a> {
+    payload: Option<PyObject<'a>>,
+}
+
+impl<'a> VariantAccess<'a> {
+    fn payload(self) -> Result<Deserializer<'a>, PyError> {
+        self.payload.map(Deserializer::new).ok_or(PyError::FromTypeConversionError)
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for VariantAccess<'a> {
+    type Error = PyError;
+
+    fn unit_variant(self) -> Result<(), PyError> {
+        match self.payload {
+            Some(ref payload) if !payload.is_none() => Err(PyError::FromTypeConversionError),
+            _ => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, PyError>
+        where T: DeserializeSeed<'de>
+    {
+        seed.deserialize(try!(self.payload()))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, PyError> {
+        de::Deserializer::deserialize_seq(try!(self.payload()), visitor)
+    }
+
+    fn struct_variant<V>(self,
+                         _fields: &'static [&'static str],
+                         visitor: V)
+                         -> Result<V::Value, PyError>
+        where V: Visitor<'de>
+    {
+        de::Deserializer::deserialize_map(try!(self.payload()), visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use base::{PyState, PyError, ToPyType};
+    use super::Serde;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Status {
+        Active,
+        Suspended(String),
+        Moved { to: u32 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: u64,
+        name: String,
+        admin: bool,
+        score: f64,
+        email: Option<String>,
+        tags: Vec<String>,
+        limits: BTreeMap<String, i32>,
+        location: (f32, f32),
+        status: Vec<Status>,
+    }
+
+    fn user() -> User {
+        let mut limits = BTreeMap::new();
+        limits.insert("upload".to_string(), 10);
+        User {
+            id: u64::MAX,
+            name: "ada".to_string(),
+            admin: true,
+            score: 0.5,
+            email: None,
+            tags: vec!["a".to_string(), "b".to_string()],
+            limits,
+            location: (1.5, -2.0),
+            status: vec![Status::Active,
+                         Status::Suspended("spam".to_string()),
+                         Status::Moved { to: 7 }],
+        }
+    }
+
+    #[test]
+    fn struct_to_py_object_and_back() {
+        let py = PyState::new();
+        let value = Serde(user());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<Serde<User>>(py_object));
+        assert_eq!(returned.0, user());
+    }
+
+    #[test]
+    fn python_representation() {
+        let py = PyState::new();
+        let value = Serde(vec![Status::Active, Status::Moved { to: 3 }]);
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{['Active', {'Moved': {'to': 3}}]}");
+        let value = Serde((true, None::<u8>, 'x'));
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        assert_eq!(format!("{:?}", py_object), "PyObject{(True, None, 'x')}");
+    }
+
+    #[test]
+    fn through_json() {
+        let py = PyState::new();
+        let json = try_or_panic!(py.get_module("json"));
+        let text: String = try_or_panic!(json.call_func_with_ret("dumps", (Serde(user()),)));
+        let returned: Serde<User> = try_or_panic!(json.call_func_with_ret("loads", (text,)));
+        assert_eq!(returned.0, user());
+    }
+
+    #[test]
+    fn missing_field_should_err() {
+        let py = PyState::new();
+        let value = Serde(Status::Moved { to: 1 });
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        match py.from_py_object::<Serde<User>>(py_object) {
+            Err(PyError::Serde(_)) => (),
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn unhashable_key_should_err() {
+        let py = PyState::new();
+        let mut map = BTreeMap::new();
+        map.insert(vec![1], 2);
+        match Serde(map).to_py_object(&py) {
+            Err(PyError::PyException(_)) => (),
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{HashSet, BTreeSet};
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::ptr;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::PythonCAPI;
+
+/// Wrapper around a `HashSet` that converts to a python `frozenset`.
+///
+/// Unlike `set`, a `frozenset` is hashable and so can be used as a dictionary
+/// key or as a member of another set. Extraction accepts both `set` and
+/// `frozenset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrozenSet<T: Eq + Hash>(pub HashSet<T>);
+
+fn set_to_py_object<'a, 'b, T, I>(state: &'a PyState, items: I) -> Result<PyObject<'a>, PyError>
+    where T: ToPyType + 'b,
+          I: Iterator<Item = &'b T>
+{
+    unsafe {
+        let raw = state.PySet_New(ptr::null_mut());
+        if raw.is_null() {
+            return Err(PyError::ToTypeConversionError);
+        }
+        let set = PyObject::new(state, raw);
+        for item in items {
+            let pyitem = try!(item.to_py_object(state));
+            if state.PySet_Add(set.raw, pyitem.raw) != 0 {
+                try!(state.get_result_exception());
+                return Err(PyError::ToTypeConversionError);
+            }
+        }
+        Ok(set)
+    }
+}
+
+fn set_from_py_object<'a, T, C>(state: &'a PyState, py_object: PyObject<'a>) -> Result<C, PyError>
+    where T: FromPyType<'a>,
+          C: FromIterator<T>
+{
+    unsafe {
+        if !py_object.raw.is_null() && state.PyAnySet_Check(py_object.raw) > 0 {
+            try!(py_object.iter::<T>()).collect()
+        } else {
+            Err(PyError::FromTypeConversionError)
+        }
+    }
+}
+
+impl<T: ToPyType + Eq + Hash> ToPyType for HashSet<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        set_to_py_object(state, self.iter())
+    }
+}
+
+impl<'a, T: FromPyType<'a> + Eq + Hash> FromPyType<'a> for HashSet<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<HashSet<T>, PyError> {
+        set_from_py_object(state, py_object)
+    }
+}
+
+impl<T: ToPyType + Ord> ToPyType for BTreeSet<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        set_to_py_object(state, self.iter())
+    }
+}
+
+impl<'a, T: FromPyType<'a> + Ord> FromPyType<'a> for BTreeSet<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<BTreeSet<T>, PyError> {
+        set_from_py_object(state, py_object)
+    }
+}
+
+impl<T: ToPyType + Eq + Hash> ToPyType for FrozenSet<T> {
+    fn to_py_object<'a>(&'a self, state: &'a PyState) -> Result<PyObject<'a>, PyError> {
+        let set = try!(set_to_py_object(state, self.0.iter()));
+        unsafe {
+            let raw = state.PyFrozenSet_New(set.raw);
+            if !raw.is_null() && state.PyFrozenSet_Check(raw) > 0 {
+                Ok(PyObject::new(state, raw))
+            } else {
+                try!(state.get_result_exception());
+                Err(PyError::ToTypeConversionError)
+            }
+        }
+    }
+}
+
+impl<'a, T: FromPyType<'a> + Eq + Hash> FromPyType<'a> for FrozenSet<T> {
+    fn from_py_object(state: &'a PyState, py_object: PyObject<'a>) -> Result<FrozenSet<T>, PyError> {
+        set_from_py_object(state, py_object).map(FrozenSet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashSet, BTreeSet};
+    use base::{PyState, ToPyType};
+    use primtypes::NoArgs;
+    use super::FrozenSet;
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn hash_set_to_py_object_and_back() {
+        let py = PyState::new();
+        let value: HashSet<isize> = vec![1, 2, 3].into_iter().collect();
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<HashSet<isize>>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn btree_set_to_py_object_and_back() {
+        let py = PyState::new();
+        let value: BTreeSet<String> = vec!["a".to_string(), "b".to_string()].into_iter().collect();
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let returned = try_or_panic!(py.from_py_object::<BTreeSet<String>>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn set_deduplicates_python_values() {
+        let py = PyState::new();
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let set = try_or_panic!(builtins.call_func("set", (vec![1, 1, 2],)));
+        let returned = try_or_panic!(py.from_py_object::<BTreeSet<isize>>(set));
+        assert_eq!(returned.into_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn frozenset_extracts_into_hash_set() {
+        let py = PyState::new();
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let frozen = try_or_panic!(builtins.call_func("frozenset", (vec![1, 2],)));
+        let returned = try_or_panic!(py.from_py_object::<HashSet<isize>>(frozen));
+        assert_eq!(returned, vec![1, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn frozen_set_is_hashable() {
+        let py = PyState::new();
+        let value = FrozenSet(vec![1, 2, 3].into_iter().collect::<HashSet<isize>>());
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let _: isize = try_or_panic!(py_object.call_func_with_ret("__hash__", NoArgs));
+        let returned = try_or_panic!(py.from_py_object::<FrozenSet<isize>>(py_object));
+        assert_eq!(returned, value);
+    }
+
+    #[test]
+    fn list_to_set_should_err() {
+        let py = PyState::new();
+        let value = vec![1, 2];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        let result = py.from_py_object::<HashSet<isize>>(py_object);
+        match result {
+            Err(_) => (),
+            Ok(x) => panic!("should have failed but got {:?}", x),
+        };
+    }
+}
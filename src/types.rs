@@ -0,0 +1,459 @@
+//! Typed handles on python objects.
+//!
+//! A handle checks the type of the object once, when it is created with
+//! `PyObject::downcast` or `cast_from`, and then reads and edits the python
+//! object in place instead of converting it to a rust container.
+
+use std::ffi::CStr;
+use std::ops::Deref;
+use libc::size_t;
+use base::{PyObject, ToPyType, FromPyType, PyState, PyError};
+use ffi::{PythonCAPI, PyObjectRaw};
+use primtypes::{bytes_to_py_object, text_from_py_object};
+
+/// Python type with a typed handle.
+pub trait PyDowncast<'a>: Sized {
+    /// Wrap `py_object` if it has the right type.
+    fn cast_from(py_object: PyObject<'a>) -> Result<Self, PyError>;
+}
+
+macro_rules! py_handle (
+  ($name:ident, $check:ident, $doc:expr) => (
+    #[doc = $doc]
+    #[derive(Debug, Clone)]
+    pub struct $name<'a>(PyObject<'a>);
+
+    impl<'a> $name<'a> {
+        /// The underlying untyped object.
+        pub fn as_object(&self) -> &PyObject<'a> {
+            &self.0
+        }
+
+        /// Give back the underlying untyped object.
+        pub fn into_object(self) -> PyObject<'a> {
+            self.0
+        }
+    }
+
+    impl<'a> Deref for $name<'a> {
+        type Target = PyObject<'a>;
+
+        fn deref(&self) -> &PyObject<'a> {
+            &self.0
+        }
+    }
+
+    impl<'a> PyDowncast<'a> for $name<'a> {
+        fn cast_from(py_object: PyObject<'a>) -> Result<$name<'a>, PyError> {
+            if !py_object.raw.is_null() && $check(py_object.state, py_object.raw) {
+                Ok($name(py_object))
+            } else {
+                Err(PyError::FromTypeConversionError)
+            }
+        }
+    }
+
+    impl<'b> ToPyType for $name<'b> {
+        fn to_py_object<'a>(&'a self, _: &'a PyState) -> Result<PyObject<'a>, PyError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    impl<'a> FromPyType<'a> for $name<'a> {
+        fn from_py_object(_: &'a PyState, py_object: PyObject<'a>) -> Result<$name<'a>, PyError> {
+            $name::cast_from(py_object)
+        }
+    }
+  )
+);
+
+fn is_list(state: &PyState, raw: *mut PyObjectRaw) -> bool {
+    unsafe { state.PyList_Check(raw) > 0 }
+}
+
+fn is_tuple(state: &PyState, raw: *mut PyObjectRaw) -> bool {
+    unsafe { state.PyTuple_Check(raw) > 0 }
+}
+
+fn is_dict(state: &PyState, raw: *mut PyObjectRaw) -> bool {
+    unsafe { state.PyDict_Check(raw) > 0 }
+}
+
+fn is_str(state: &PyState, raw: *mut PyObjectRaw) -> bool {
+    unsafe { state.PyString_Check(raw) > 0 || state.PyUnicode_Check(raw) > 0 }
+}
+
+fn is_module(state: &PyState, raw: *mut PyObjectRaw) -> bool {
+    unsafe { state.PyModule_Check(raw) > 0 }
+}
+
+py_handle!(PyList, is_list, "Handle on a python `list`.");
+py_handle!(PyTuple, is_tuple, "Handle on a python `tuple`.");
+py_handle!(PyDict, is_dict, "Handle on a python `dict`.");
+py_handle!(PyStr, is_str, "Handle on a python `str` or `unicode`.");
+py_handle!(PyModule, is_module, "Handle on a python module.");
+
+/// Wrap a new reference, turning a null pointer into the pending exception.
+fn new_reference<'a>(state: &'a PyState, raw: *mut PyObjectRaw) -> Result<PyObject<'a>, PyError> {
+    if raw.is_null() {
+        try!(state.get_result_exception());
+        Err(PyError::NullPyObject)
+    } else {
+        Ok(PyObject::new(state, raw))
+    }
+}
+
+/// Wrap a borrowed reference, turning a null pointer into the pending
+/// exception.
+fn borrowed_reference<'a>(state: &'a PyState,
+                          raw: *mut PyObjectRaw)
+                          -> Result<PyObject<'a>, PyError> {
+    if !raw.is_null() {
+        unsafe {
+            state.Py_IncRef(raw);
+        }
+    }
+    new_reference(state, raw)
+}
+
+/// Turn the status code of a C API call into the pending exception.
+fn check_status(state: &PyState, status: i32) -> Result<(), PyError> {
+    if status < 0 {
+        try!(state.get_result_exception());
+        Err(PyError::NullPyObject)
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a> PyList<'a> {
+    /// Create an empty list.
+    pub fn new(state: &'a PyState) -> Result<PyList<'a>, PyError> {
+        unsafe { new_reference(state, state.PyList_New(0)).map(PyList) }
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { self.state.PyList_Size(self.raw) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the item at `index`, failing with an `IndexError` when out of range.
+    pub fn get_item(&self, index: usize) -> Result<PyObject<'a>, PyError> {
+        unsafe {
+            borrowed_reference(self.state, self.state.PyList_GetItem(self.raw, index as size_t))
+        }
+    }
+
+    /// Replace the item at `index`, failing with an `IndexError` when out of range.
+    pub fn set_item<T: ToPyType + ?Sized>(&self, index: usize, value: &T) -> Result<(), PyError> {
+        let py_value = try!(value.to_py_object(self.state));
+        unsafe {
+            if index >= self.len() {
+                // Raise the IndexError of python for a consistent message.
+                return self.get_item(index).map(|_| ());
+            }
+            self.state.Py_IncRef(py_value.raw);
+            self.state.PyList_SetItem(self.raw, index as size_t, py_value.raw);
+        }
+        Ok(())
+    }
+
+    /// Add an item at the end of the list.
+    pub fn append<T: ToPyType + ?Sized>(&self, value: &T) -> Result<(), PyError> {
+        let py_value = try!(value.to_py_object(self.state));
+        unsafe { check_status(self.state, self.state.PyList_Append(self.raw, py_value.raw)) }
+    }
+
+    /// Insert an item before `index`, or at the end when `index` is past it.
+    pub fn insert<T: ToPyType + ?Sized>(&self, index: usize, value: &T) -> Result<(), PyError> {
+        let py_value = try!(value.to_py_object(self.state));
+        unsafe {
+            let status = self.state.PyList_Insert(self.raw, index as size_t, py_value.raw);
+            check_status(self.state, status)
+        }
+    }
+}
+
+impl<'a> PyTuple<'a> {
+    pub fn len(&self) -> usize {
+        unsafe { self.state.PyTuple_Size(self.raw) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the item at `index`, failing with an `IndexError` when out of range.
+    pub fn get_item(&self, index: usize) -> Result<PyObject<'a>, PyError> {
+        unsafe {
+            borrowed_reference(self.state, self.state.PyTuple_GetItem(self.raw, index as size_t))
+        }
+    }
+}
+
+impl<'a> PyDict<'a> {
+    /// Create an empty dictionary.
+    pub fn new(state: &'a PyState) -> Result<PyDict<'a>, PyError> {
+        unsafe { new_reference(state, state.PyDict_New()).map(PyDict) }
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { self.state.PyDict_Size(self.raw) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether `key` is in the dictionary. Unhashable keys are an error.
+    pub fn contains<K: ToPyType + ?Sized>(&self, key: &K) -> Result<bool, PyError> {
+        let py_key = try!(key.to_py_object(self.state));
+        unsafe {
+            let result = self.state.PyDict_Contains(self.raw, py_key.raw);
+            try!(check_status(self.state, result));
+            Ok(result > 0)
+        }
+    }
+
+    /// Get the value for `key`, or `None` if it is missing.
+    pub fn get<K: ToPyType + ?Sized>(&self, key: &K) -> Result<Option<PyObject<'a>>, PyError> {
+        let py_key = try!(key.to_py_object(self.state));
+        unsafe {
+            // PyDict_GetItem hides errors, so check for unhashable keys first.
+            try!(check_status(self.state, self.state.PyDict_Contains(self.raw, py_key.raw)));
+            let raw = self.state.PyDict_GetItem(self.raw, py_key.raw);
+            if raw.is_null() {
+                Ok(None)
+            } else {
+                borrowed_reference(self.state, raw).map(Some)
+            }
+        }
+    }
+
+    /// Set the value for `key`, replacing any previous one.
+    pub fn set_item<K, V>(&self, key: &K, value: &V) -> Result<(), PyError>
+        where K: ToPyType + ?Sized,
+              V: ToPyType + ?Sized
+    {
+        let py_key = try!(key.to_py_object(self.state));
+        let py_value = try!(value.to_py_object(self.state));
+        unsafe {
+            let status = self.state.PyDict_SetItem(self.raw, py_key.raw, py_value.raw);
+            check_status(self.state, status)
+        }
+    }
+
+    /// Remove `key`, failing with a `KeyError` when it is missing.
+    pub fn del_item<K: ToPyType + ?Sized>(&self, key: &K) -> Result<(), PyError> {
+        let py_key = try!(key.to_py_object(self.state));
+        unsafe { check_status(self.state, self.state.PyDict_DelItem(self.raw, py_key.raw)) }
+    }
+
+    /// New list of the keys.
+    pub fn keys(&self) -> Result<PyList<'a>, PyError> {
+        unsafe { new_reference(self.state, self.state.PyDict_Keys(self.raw)).map(PyList) }
+    }
+
+    /// New list of the values.
+    pub fn values(&self) -> Result<PyList<'a>, PyError> {
+        unsafe { new_reference(self.state, self.state.PyDict_Values(self.raw)).map(PyList) }
+    }
+
+    /// New list of the `(key, value)` tuples.
+    pub fn items(&self) -> Result<PyList<'a>, PyError> {
+        unsafe { new_reference(self.state, self.state.PyDict_Items(self.raw)).map(PyList) }
+    }
+
+    /// Copy every entry of `other`, a dictionary or any object with `keys()`
+    /// and item access, like python's `dict.update`.
+    pub fn update(&self, other: &PyObject) -> Result<(), PyError> {
+        unsafe { check_status(self.state, self.state.PyDict_Update(self.raw, other.raw)) }
+    }
+}
+
+impl<'a> PyStr<'a> {
+    /// Create a `str` holding the UTF-8 bytes of `value`.
+    pub fn new(state: &'a PyState, value: &str) -> Result<PyStr<'a>, PyError> {
+        bytes_to_py_object(state, value.as_bytes()).map(PyStr)
+    }
+
+    /// Check whether this is a `unicode` rather than a byte `str`.
+    pub fn is_unicode(&self) -> bool {
+        unsafe { self.state.PyUnicode_Check(self.raw) > 0 }
+    }
+
+    /// Copy the text out, encoding a `unicode` to UTF-8.
+    pub fn to_string(&self) -> Result<String, PyError> {
+        text_from_py_object(self.state, self)
+    }
+}
+
+impl<'a> PyModule<'a> {
+    /// Import the module `name`.
+    pub fn import(state: &'a PyState, name: &str) -> Result<PyModule<'a>, PyError> {
+        state.get_module(name).and_then(PyModule::cast_from)
+    }
+
+    /// The `__name__` of the module.
+    pub fn name(&self) -> Result<String, PyError> {
+        unsafe {
+            let name = self.state.PyModule_GetName(self.raw);
+            if name.is_null() {
+                try!(self.state.get_result_exception());
+                return Err(PyError::NullPyObject);
+            }
+            CStr::from_ptr(name)
+                .to_str()
+                .map(String::from)
+                .map_err(|_| PyError::StringConversionError)
+        }
+    }
+
+    /// The namespace of the module, edited in place.
+    pub fn dict(&self) -> Result<PyDict<'a>, PyError> {
+        unsafe { borrowed_reference(self.state, self.state.PyModule_GetDict(self.raw)).map(PyDict) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{PyState, PyError, PyObject, ToPyType};
+    use mapping::OrderedMap;
+    use primtypes::NoArgs;
+    use super::{PyList, PyTuple, PyDict, PyStr, PyModule, PyDowncast};
+    macro_rules! try_or_panic (
+      ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+  );
+
+    #[test]
+    fn edit_list_in_place() {
+        let py = PyState::new();
+        let source = "numbers = list(range(100000))\n";
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_list_edit", source));
+        let numbers: PyList = try_or_panic!(module.get_member("numbers"));
+        assert_eq!(numbers.len(), 100000);
+        try_or_panic!(numbers.append(&100000));
+        try_or_panic!(numbers.insert(0, "first"));
+        try_or_panic!(numbers.set_item(1, &-1));
+        let first: String = try_or_panic!(py.from_py_object(try_or_panic!(numbers.get_item(0))));
+        assert_eq!(&first, "first");
+
+        let numbers: PyObject = try_or_panic!(module.get_member_obj("numbers"));
+        let length: usize = try_or_panic!(numbers.call_func_with_ret("__len__", NoArgs));
+        assert_eq!(length, 100002);
+        let last: isize = try_or_panic!(numbers.call_func_with_ret("pop", NoArgs));
+        assert_eq!(last, 100000);
+        let second: isize = try_or_panic!(numbers.call_func_with_ret("__getitem__", (1,)));
+        assert_eq!(second, -1);
+    }
+
+    #[test]
+    fn list_index_out_of_range() {
+        let py = PyState::new();
+        let list = try_or_panic!(PyList::new(&py));
+        assert!(list.is_empty());
+        match list.get_item(0) {
+            Err(PyError::PyException(ref message)) => assert!(message.starts_with("IndexError")),
+            result => panic!("expected an IndexError but got {:?}", result),
+        }
+        match list.set_item(3, &1) {
+            Err(PyError::PyException(ref message)) => assert!(message.starts_with("IndexError")),
+            result => panic!("expected an IndexError but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn tuple_items() {
+        let py = PyState::new();
+        let py_object = try_or_panic!((1, "two").to_py_object(&py));
+        let tuple: PyTuple = try_or_panic!(py_object.downcast());
+        assert_eq!(tuple.len(), 2);
+        let two: String = try_or_panic!(py.from_py_object(try_or_panic!(tuple.get_item(1))));
+        assert_eq!(&two, "two");
+        assert!(tuple.get_item(2).is_err());
+    }
+
+    #[test]
+    fn edit_dict_in_place() {
+        let py = PyState::new();
+        let dict = try_or_panic!(PyDict::new(&py));
+        try_or_panic!(dict.set_item("a", &1));
+        try_or_panic!(dict.set_item(&2, "b"));
+        assert_eq!(dict.len(), 2);
+        assert!(try_or_panic!(dict.contains("a")));
+        let a: isize = try_or_panic!(py.from_py_object(try_or_panic!(dict.get("a")).unwrap()));
+        assert_eq!(a, 1);
+        assert!(try_or_panic!(dict.get("missing")).is_none());
+
+        let other = OrderedMap(vec![("a".to_string(), 10), ("c".to_string(), 30)]);
+        try_or_panic!(dict.update(&try_or_panic!(other.to_py_object(&py))));
+        assert_eq!(dict.len(), 3);
+        try_or_panic!(dict.del_item(&2));
+        assert!(dict.del_item(&2).is_err());
+
+        let items = try_or_panic!(dict.items()).into_object();
+        let mut items: Vec<(String, isize)> = try_or_panic!(py.from_py_object(items));
+        items.sort();
+        assert_eq!(items, vec![("a".to_string(), 10), ("c".to_string(), 30)]);
+        let keys = try_or_panic!(dict.keys()).into_object();
+        let mut keys: Vec<String> = try_or_panic!(py.from_py_object(keys));
+        keys.sort();
+        assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+        let values = try_or_panic!(dict.values());
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn unhashable_dict_key_should_err() {
+        let py = PyState::new();
+        let dict = try_or_panic!(PyDict::new(&py));
+        let key = vec![1, 2];
+        assert!(dict.get(&key).is_err());
+        assert!(dict.contains(&key).is_err());
+        assert!(dict.set_item(&key, &3).is_err());
+    }
+
+    #[test]
+    fn str_and_unicode() {
+        let py = PyState::new();
+        let text = try_or_panic!(PyStr::new(&py, "caf\u{e9}"));
+        assert!(!text.is_unicode());
+        let unicode = try_or_panic!(text.call_func("decode", ("utf-8",)));
+        let unicode: PyStr = try_or_panic!(unicode.downcast());
+        assert!(unicode.is_unicode());
+        assert_eq!(try_or_panic!(unicode.to_string()), "caf\u{e9}");
+    }
+
+    #[test]
+    fn module_namespace() {
+        let py = PyState::new();
+        let module = try_or_panic!(PyModule::import(&py, "os.path"));
+        assert_eq!(try_or_panic!(module.name()), "posixpath");
+        let namespace = try_or_panic!(module.dict());
+        assert!(try_or_panic!(namespace.contains("join")));
+    }
+
+    #[test]
+    fn wrong_type_should_err() {
+        let py = PyState::new();
+        let py_object = try_or_panic!(3isize.to_py_object(&py));
+        assert!(py_object.downcast::<PyList>().is_err());
+        assert!(py_object.downcast::<PyDict>().is_err());
+        assert!(py_object.downcast::<PyStr>().is_err());
+        assert!(PyModule::cast_from(py_object).is_err());
+    }
+
+    #[test]
+    fn handles_pass_to_python() {
+        let py = PyState::new();
+        let list = try_or_panic!(PyList::new(&py));
+        try_or_panic!(list.append(&3));
+        try_or_panic!(list.append(&4));
+        let builtins = try_or_panic!(py.get_module("__builtin__"));
+        let total: isize = try_or_panic!(builtins.call_func_with_ret("sum", (list,)));
+        assert_eq!(total, 7);
+    }
+}
@@ -0,0 +1,106 @@
+extern crate rustpy;
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use rustpy::{PyState, PyStateBuilder, PyError, PyDict};
+
+macro_rules! try_or_panic (
+  ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+);
+
+// Every test starts its own interpreter, run one test at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn sys_flag(py: &PyState, name: &str) -> isize {
+    let sys = try_or_panic!(py.get_module("sys"));
+    let flags = try_or_panic!(sys.get_member_obj("flags"));
+    try_or_panic!(flags.get_member(name))
+}
+
+fn has_module(py: &PyState, name: &str) -> bool {
+    let sys = try_or_panic!(py.get_module("sys"));
+    let modules = try_or_panic!(sys.get_member_obj("modules"));
+    try_or_panic!(try_or_panic!(modules.downcast::<PyDict>()).contains(name))
+}
+
+#[test]
+fn argv_and_sys_path() {
+    let _serial = serial();
+    let py = try_or_panic!(PyStateBuilder::new()
+                               .argv(&["host", "-v"])
+                               .prepend_path("/rustpy/first")
+                               .prepend_path(Path::new("/rustpy/second"))
+                               .build());
+    {
+        let sys = try_or_panic!(py.get_module("sys"));
+        let argv: Vec<String> = try_or_panic!(sys.get_member("argv"));
+        assert_eq!(argv, vec!["host", "-v"]);
+        let path: Vec<String> = try_or_panic!(sys.get_member("path"));
+        assert_eq!(&path[..2], &["/rustpy/first", "/rustpy/second"]);
+    }
+    py.finalize();
+}
+
+#[test]
+fn interpreter_flags() {
+    let _serial = serial();
+    let py = try_or_panic!(PyStateBuilder::new()
+                               .optimize(2)
+                               .dont_write_bytecode(true)
+                               .no_site(true)
+                               .build());
+    assert_eq!(sys_flag(&py, "optimize"), 2);
+    assert_eq!(sys_flag(&py, "dont_write_bytecode"), 1);
+    assert_eq!(sys_flag(&py, "no_site"), 1);
+    assert!(!has_module(&py, "site"));
+    py.finalize();
+
+    // Plain states start with the default flags again.
+    let py = PyState::new();
+    assert_eq!(sys_flag(&py, "optimize"), 0);
+    assert_eq!(sys_flag(&py, "no_site"), 0);
+    assert!(has_module(&py, "site"));
+    py.finalize();
+}
+
+#[test]
+fn signal_handlers() {
+    let _serial = serial();
+    let py = try_or_panic!(PyStateBuilder::new().signal_handlers(false).build());
+    assert!(!has_module(&py, "signal"));
+    py.finalize();
+
+    let py = try_or_panic!(PyStateBuilder::new().build());
+    assert!(has_module(&py, "signal"));
+    py.finalize();
+}
+
+#[test]
+fn build_while_running_should_err() {
+    let _serial = serial();
+    let py = PyState::new();
+    match PyStateBuilder::new().no_site(true).build() {
+        Err(PyError::AlreadyInitialized) => (),
+        Err(error) => panic!("expected an initialized interpreter but got {:?}", error),
+        Ok(_) => panic!("expected an initialized interpreter"),
+    };
+    py.finalize();
+    let py = try_or_panic!(PyStateBuilder::new().no_site(true).build());
+    assert_eq!(sys_flag(&py, "no_site"), 1);
+    py.finalize();
+}
+
+#[test]
+fn paths_are_fixed_by_the_first_interpreter() {
+    let _serial = serial();
+    PyState::new().finalize();
+    match PyStateBuilder::new().program_name("python").build() {
+        Err(PyError::AlreadyInitialized) => (),
+        Err(error) => panic!("expected fixed paths but got {:?}", error),
+        Ok(_) => panic!("expected fixed paths"),
+    };
+}
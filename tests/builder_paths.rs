@@ -0,0 +1,30 @@
+extern crate rustpy;
+
+use std::env;
+use std::path::Path;
+use rustpy::PyStateBuilder;
+
+macro_rules! try_or_panic (
+  ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+);
+
+// Python computes its paths once per process, this must be the only test.
+#[test]
+fn program_name_and_home() {
+    let home = env::var("PYTHONHOME").unwrap_or_else(|_| "/usr".to_string());
+    let executable = Path::new(&home).join("bin").join("python2.7");
+    let py = try_or_panic!(PyStateBuilder::new()
+                               .program_name(executable.to_str().unwrap())
+                               .python_home(&home)
+                               .ignore_environment(true)
+                               .build());
+    let sys = try_or_panic!(py.get_module("sys"));
+    let flags = try_or_panic!(sys.get_member_obj("flags"));
+    assert_eq!(try_or_panic!(flags.get_member::<isize>("ignore_environment")), 1);
+    let prefix: String = try_or_panic!(sys.get_member("prefix"));
+    assert_eq!(prefix, home);
+    if executable.exists() {
+        let name: String = try_or_panic!(sys.get_member("executable"));
+        assert_eq!(Path::new(&name), executable);
+    }
+}
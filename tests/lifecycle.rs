@@ -0,0 +1,136 @@
+extern crate rustpy;
+
+use std::env;
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+use rustpy::{PyState, PyError, OwnedPyObject, ToPyType, NoArgs};
+
+macro_rules! try_or_panic (
+  ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+);
+
+// Finalizing waits for the states of every thread, and `try_new` fails while
+// another thread holds a state, run one test at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn has_marker(py: &PyState) -> bool {
+    let sys = try_or_panic!(py.get_module("sys"));
+    let builtins = try_or_panic!(py.get_module("__builtin__"));
+    let marker: isize = try_or_panic!(builtins.call_func_with_ret("getattr",
+                                                                  (sys, "_rustpy_marker", 0)));
+    marker == 1
+}
+
+fn set_marker(py: &PyState) {
+    let sys = try_or_panic!(py.get_module("sys"));
+    let builtins = try_or_panic!(py.get_module("__builtin__"));
+    try_or_panic!(builtins.call_func("setattr", (sys, "_rustpy_marker", 1)));
+}
+
+#[test]
+fn interpreter_outlives_state_by_default() {
+    let _serial = serial();
+    {
+        let py = PyState::new();
+        set_marker(&py);
+    }
+    let py = PyState::new();
+    assert!(has_marker(&py));
+    py.finalize();
+}
+
+#[test]
+fn finalize_starts_a_fresh_interpreter() {
+    let _serial = serial();
+    let py = PyState::new();
+    set_marker(&py);
+    py.finalize();
+
+    let py = PyState::new();
+    assert!(!has_marker(&py));
+    let math = try_or_panic!(py.get_module("math"));
+    let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (16.0,)));
+    assert_eq!(root, 4.0);
+}
+
+#[test]
+fn try_new_nested_on_one_thread() {
+    let _serial = serial();
+    let py = PyState::new();
+    {
+        let nested = try_or_panic!(PyState::try_new());
+        let math = try_or_panic!(nested.get_module("math"));
+        let root: f64 = try_or_panic!(math.call_func_with_ret("sqrt", (9.0,)));
+        assert_eq!(root, 3.0);
+    }
+    try_or_panic!(py.get_module("math"));
+}
+
+#[test]
+fn finalize_on_drop() {
+    let _serial = serial();
+    {
+        let mut py = PyState::new();
+        py.finalize_on_drop(true);
+        set_marker(&py);
+    }
+    let py = PyState::new();
+    assert!(!has_marker(&py));
+}
+
+#[test]
+fn finalize_runs_atexit_handlers() {
+    let _serial = serial();
+    let path = env::temp_dir().join(format!("rustpy_atexit_{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let py = PyState::new();
+    let source = concat!("import atexit\n",
+                         "def register(path):\n",
+                         "    def write():\n",
+                         "        with open(path, 'w') as f:\n",
+                         "            f.write('done')\n",
+                         "    atexit.register(write)\n");
+    {
+        let module = try_or_panic!(py.module_from_source("_rustpy_test_atexit", source));
+        try_or_panic!(module.call_func("register", (path.to_str().unwrap(),)));
+    }
+    assert!(!path.exists());
+    py.finalize();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "done");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn finalize_at_exit_registers_once() {
+    let _serial = serial();
+    PyState::finalize_at_exit();
+    PyState::finalize_at_exit();
+    let py = PyState::new();
+    let sys = try_or_panic!(py.get_module("sys"));
+    let stdout = try_or_panic!(sys.get_member_obj("stdout"));
+    try_or_panic!(stdout.call_func("flush", NoArgs));
+}
+
+#[test]
+fn owned_object_outliving_interpreter_should_err() {
+    let _serial = serial();
+    let py = PyState::new();
+    let owned = {
+        let value = vec![1isize, 2];
+        let py_object = try_or_panic!(value.to_py_object(&py));
+        try_or_panic!(OwnedPyObject::new(&py_object))
+    };
+    py.finalize();
+
+    let py = PyState::new();
+    match owned.bind(&py) {
+        Err(PyError::InvalidOwnedObject(_)) => (),
+        result => panic!("expected an invalid owned object but got {:?}", result),
+    };
+    drop(owned);
+}
@@ -0,0 +1,159 @@
+extern crate rustpy;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+use rustpy::{PyState, PyStateBuilder, PyError};
+
+macro_rules! try_or_panic (
+  ($e:expr) => (match $e { Ok(e) => e, Err(e) => panic!("{:?}", e) })
+);
+
+// Every test starts its own interpreter, run one test at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Virtualenv removed when dropped.
+struct Virtualenv(PathBuf);
+
+impl Virtualenv {
+    /// Lay out a virtualenv with one module in `site-packages` and one in a
+    /// directory added by a `.pth` file.
+    ///
+    /// Only the directories activation reads are created, without the
+    /// `virtualenv` tool, which may not be installed. The interpreter stays the
+    /// embedded one either way. `created_virtualenv_packages_are_importable`
+    /// checks a virtualenv made by the tool.
+    fn create(name: &str) -> Virtualenv {
+        let base = env::temp_dir().join(format!("rustpy_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let site_packages = base.join("lib").join("python2.7").join("site-packages");
+        let extra = base.join("extra");
+        fs::create_dir_all(&site_packages).unwrap();
+        fs::create_dir_all(base.join("bin")).unwrap();
+        fs::create_dir_all(&extra).unwrap();
+        fs::write(site_packages.join("rustpy_venv_module.py"), "VALUE = 42\n").unwrap();
+        fs::write(site_packages.join("extra.pth"), format!("{}\n", extra.display())).unwrap();
+        fs::write(extra.join("rustpy_pth_module.py"), "VALUE = 7\n").unwrap();
+        Virtualenv(base)
+    }
+
+    fn site_packages(&self) -> PathBuf {
+        self.0.join("lib").join("python2.7").join("site-packages")
+    }
+}
+
+impl Drop for Virtualenv {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `virtualenv`, or the `virtualenv` module of `python2.7`, whichever runs.
+fn virtualenv_command() -> Option<Command> {
+    let candidates: [(&str, &[&str]); 2] = [("virtualenv", &["-p", "python2.7"]),
+                                            ("python2.7", &["-m", "virtualenv"])];
+    candidates.iter()
+              .find(|&&(program, args)| {
+                  Command::new(program)
+                      .args(args)
+                      .arg("--version")
+                      .stdout(Stdio::null())
+                      .stderr(Stdio::null())
+                      .status()
+                      .map(|status| status.success())
+                      .unwrap_or(false)
+              })
+              .map(|&(program, args)| {
+                  let mut command = Command::new(program);
+                  command.args(args);
+                  command
+              })
+}
+
+fn import_value(py: &PyState, module: &str) -> Result<isize, PyError> {
+    py.get_module(module).and_then(|module| module.get_member("VALUE"))
+}
+
+#[test]
+fn virtualenv_packages_are_importable() {
+    let _serial = serial();
+    let venv = Virtualenv::create("venv");
+    let py = try_or_panic!(PyStateBuilder::new().virtualenv(&venv.0).build());
+    assert_eq!(try_or_panic!(import_value(&py, "rustpy_venv_module")), 42);
+    assert_eq!(try_or_panic!(import_value(&py, "rustpy_pth_module")), 7);
+    {
+        let sys = try_or_panic!(py.get_module("sys"));
+        let prefix: PathBuf = try_or_panic!(sys.get_member("prefix"));
+        assert_eq!(prefix, venv.0);
+        let real_prefix: String = try_or_panic!(sys.get_member("real_prefix"));
+        assert!(Path::new(&real_prefix) != venv.0);
+        let path: Vec<PathBuf> = try_or_panic!(sys.get_member("path"));
+        assert_eq!(path[0], venv.site_packages());
+        assert_eq!(path[1], venv.0.join("extra"));
+        let os = try_or_panic!(py.get_module("os"));
+        let environ = try_or_panic!(os.get_member_obj("environ"));
+        let path: String = try_or_panic!(environ.call_func_with_ret("get", ("PATH",)));
+        let bin = venv.0.join("bin");
+        assert!(path.starts_with(bin.to_str().unwrap()), "{}", path);
+    }
+    py.finalize();
+
+    let py = PyState::new();
+    assert!(import_value(&py, "rustpy_venv_module").is_err());
+    py.finalize();
+}
+
+#[test]
+#[ignore = "needs the virtualenv tool for python 2.7, run with --ignored where it is installed"]
+fn created_virtualenv_packages_are_importable() {
+    let _serial = serial();
+    let mut command = virtualenv_command().expect("neither `virtualenv` nor `python2.7 -m \
+                                                   virtualenv` could be run");
+    let base = env::temp_dir().join(format!("rustpy_created_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let venv = Virtualenv(base);
+    let status = command.arg(&venv.0).stdout(Stdio::null()).status().unwrap();
+    assert!(status.success(), "creating the virtualenv failed with {}", status);
+    fs::write(venv.site_packages().join("rustpy_venv_module.py"), "VALUE = 42\n").unwrap();
+
+    let py = try_or_panic!(PyStateBuilder::new().virtualenv(&venv.0).build());
+    assert_eq!(try_or_panic!(import_value(&py, "rustpy_venv_module")), 42);
+    {
+        let sys = try_or_panic!(py.get_module("sys"));
+        let prefix: PathBuf = try_or_panic!(sys.get_member("prefix"));
+        assert_eq!(prefix, venv.0);
+    }
+    py.finalize();
+}
+
+#[test]
+fn missing_virtualenv_should_err() {
+    let _serial = serial();
+    let missing = env::temp_dir().join(format!("rustpy_missing_{}", std::process::id()));
+    match PyStateBuilder::new().virtualenv(&missing).build() {
+        Err(PyError::Virtualenv(ref message)) => assert!(message.contains("rustpy_missing")),
+        Err(error) => panic!("expected an invalid virtualenv but got {:?}", error),
+        Ok(_) => panic!("expected an invalid virtualenv"),
+    };
+}
+
+#[test]
+fn virtualenv_without_site_packages_should_err() {
+    let _serial = serial();
+    let venv = Virtualenv::create("broken");
+    fs::remove_dir_all(venv.0.join("lib")).unwrap();
+    match PyStateBuilder::new().virtualenv(&venv.0).build() {
+        Err(PyError::Virtualenv(ref message)) => assert!(message.contains("site-packages")),
+        Err(error) => panic!("expected an invalid virtualenv but got {:?}", error),
+        Ok(_) => panic!("expected an invalid virtualenv"),
+    };
+    // The failed activation does not leave the interpreter running.
+    let py = try_or_panic!(PyStateBuilder::new().build());
+    py.finalize();
+}